@@ -50,7 +50,7 @@ const WINDOW_DIMENSIONS: (i32, i32) = (300, 200);
 
 impl Editor for MyPluginEditor {
     fn size(&self) -> (i32, i32) {
-        (WINDOW_DIMENSIONS.0 as i32, WINDOW_DIMENSIONS.1 as i32)
+        WINDOW_DIMENSIONS
     }
 
     fn position(&self) -> (i32, i32) {
@@ -73,16 +73,15 @@ impl Editor for MyPluginEditor {
     }
 
     fn close(&mut self) {
-        drop(self.renderer.take());
-        drop(self.window_events.take());
+        self.renderer = None;
+        self.window_events = None;
     }
 
     fn idle(&mut self) {
         if let Some(window_events) = &mut self.window_events {
             while let Some(event) = window_events.poll_event() {
-                match event {
-                    WindowEvent::MouseClick(_) => println!("Click!"),
-                    _ => (),
+                if let WindowEvent::MouseClick(_) = event {
+                    println!("Click!");
                 }
             }
         }
@@ -95,9 +94,17 @@ impl Editor for MyPluginEditor {
 struct MyRenderer;
 
 impl MyRenderer {
+    #[cfg(not(all(feature = "x11rb", not(feature = "xcb"))))]
     pub fn new<W: raw_window_handle::HasRawWindowHandle>(_handle: W) -> Self {
         Self
     }
+    /// The `x11rb` backend has no raw window handle, so renderers draw to the window's id over
+    /// their own connection to the X server instead.
+    #[cfg(all(feature = "x11rb", not(feature = "xcb")))]
+    pub fn new(window: vst_window::EditorWindow) -> Self {
+        let _window_id = window.x11_window_id();
+        Self
+    }
     pub fn draw_frame(&mut self) {
         /* ... */
     }
@@ -1,7 +1,11 @@
 //! Cross-platform type abstractions over low-level platform-specific window events.
 
+use std::path::PathBuf;
+use std::time::Duration;
+
 /// Represents an interaction with an editor window.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WindowEvent {
     /// XY coordinates. Each coordinate is based in the range [0, 1], scaled to the bounds of the
     /// window. Origin is at the top-left. The coordinates could be outside of the range if the
@@ -9,10 +13,64 @@ pub enum WindowEvent {
     CursorMovement(f32, f32),
     MouseClick(MouseButton),
     MouseRelease(MouseButton),
+    /// A key was pressed. `keycode` identifies the physical key, and is platform-specific: on
+    /// X11, it is the X keycode. `repeat` is `true` for the presses generated automatically while
+    /// the key is held down.
+    KeyPress {
+        keycode: u32,
+        repeat: bool,
+    },
+    /// A key was released. A held key is only released once, after its repeated `KeyPress`
+    /// events.
+    KeyRelease {
+        keycode: u32,
+    },
+    /// The editor gained (`true`) or lost (`false`) the keyboard focus. Currently only emitted
+    /// when an XEmbed embedder hands over the focus, once enabled with
+    /// `EventSource::set_xembed_enabled`.
+    Focused(bool),
+    /// Emitted at the rate requested with `EventSource::set_frame_rate`, independently of how
+    /// often the host calls `Editor::idle`. `timestamp` is the time since the frame timer was
+    /// started, and `delta` is the time since the previous `Frame`.
+    Frame {
+        timestamp: Duration,
+        delta: Duration,
+    },
+    /// A payload sent from another thread through a `Waker`.
+    User(u64),
+    /// Files are being dragged over the window, at the given XY coordinates, scaled like those of
+    /// `CursorMovement`. Only emitted once file drops have been enabled with
+    /// `EventSource::set_file_drop_enabled`.
+    FileHovered(f32, f32),
+    /// Files were dropped onto the window.
+    FileDropped(Vec<PathBuf>),
+    /// Files are no longer being dragged over the window, or the drop was rejected or failed.
+    FileHoverCancelled,
+    /// A drag started with `EventSource::start_drag` has ended. `accepted` is `true` if the data
+    /// was dropped onto another application, which accepted it.
+    DragEnded {
+        accepted: bool,
+    },
+    /// A mouse button was pressed outside of a popup opened with `EditorWindow::create_popup`,
+    /// which should now be closed. Only emitted by the popup's `EventSource`.
+    PopupDismissed,
+    /// The item with the given id was chosen from a `ContextMenu`, which has been closed. Only
+    /// emitted by `ContextMenu::poll_event`.
+    MenuItemSelected(u32),
+    /// An input method is composing `text`, which should be drawn in place of the text cursor
+    /// until it is committed, e.g. with an underline. `cursor` is the byte offset of the caret
+    /// within `text`. An empty `text` means composition has ended or been cancelled.
+    ImePreedit {
+        text: String,
+        cursor: usize,
+    },
+    /// An input method has finished composing text, which should be inserted at the text cursor.
+    ImeCommit(String),
 }
 
 /// Represents one of the buttons on a mouse.
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MouseButton {
     Left,
     Right,
@@ -1,7 +1,38 @@
 //! `vst_window` provides a cross-platform API for implementing VST plugin editor windows.
 
 mod event;
+mod menu;
 mod platform;
+#[cfg(feature = "record")]
+mod record;
+#[cfg(all(
+    feature = "async",
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    )
+))]
+mod stream;
 
 pub use event::{MouseButton, WindowEvent};
-pub use platform::{setup, EditorWindow, EventSource};
+pub use menu::{ContextMenu, MenuItem};
+pub use platform::{
+    setup, Clipboard, DragData, EditorWindow, EventSource, Image, PopupWindow, PresentMethod, Rect,
+    Waker,
+};
+#[cfg(feature = "record")]
+pub use record::{EventRecorder, ReplayEventSource};
+#[cfg(all(
+    feature = "async",
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    )
+))]
+pub use stream::EventStream;
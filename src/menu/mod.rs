@@ -0,0 +1,315 @@
+//! A minimal context menu, for the right-click menus of plugin parameters ("MIDI learn", "Reset",
+//! ...) without depending on a GUI toolkit.
+//!
+//! Menus are drawn in software with `EditorWindow::present_pixels`, onto a popup window opened with
+//! `EditorWindow::create_popup`. Labels are drawn with a small built-in bitmap font, which only
+//! covers printable ASCII.
+
+mod font;
+
+use std::cell::{Cell, RefCell};
+
+use crate::event::WindowEvent;
+use crate::platform::{EditorWindow, EventSource, PopupWindow, Rect};
+
+/// Each font pixel is drawn as a square of this many pixels.
+const FONT_SCALE: u32 = 2;
+/// The horizontal distance between the starts of consecutive characters, in pixels.
+const ADVANCE: u32 = (font::WIDTH + 1) * FONT_SCALE;
+const BORDER: u32 = 1;
+/// The space between the border and the first or last row.
+const VERTICAL_PADDING: u32 = 3;
+/// The space between the border and the labels.
+const HORIZONTAL_PADDING: u32 = 12;
+const ITEM_HEIGHT: u32 = 24;
+const SEPARATOR_HEIGHT: u32 = 9;
+const MIN_WIDTH: u32 = 120;
+
+const BACKGROUND_COLOR: u32 = 0x00f0_f0f0;
+const BORDER_COLOR: u32 = 0x0080_8080;
+const TEXT_COLOR: u32 = 0x0020_2020;
+const HIGHLIGHT_COLOR: u32 = 0x0038_75d7;
+const HIGHLIGHTED_TEXT_COLOR: u32 = 0x00ff_ffff;
+const SEPARATOR_COLOR: u32 = 0x00c8_c8c8;
+
+/// An entry of a `ContextMenu`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MenuItem {
+    /// An item that can be chosen, which is reported by its `id` in
+    /// `WindowEvent::MenuItemSelected`.
+    Item { id: u32, label: String },
+    /// A horizontal line between groups of items.
+    Separator,
+}
+
+impl MenuItem {
+    /// Shorthand for `MenuItem::Item`.
+    pub fn new(id: u32, label: impl Into<String>) -> Self {
+        MenuItem::Item {
+            id,
+            label: label.into(),
+        }
+    }
+}
+
+/// A context menu shown with `ContextMenu::show`, which lists items for the user to choose from.
+///
+/// The menu is closed once an item is chosen or a mouse button is pressed outside of it, or when
+/// it is dropped.
+pub struct ContextMenu {
+    items: Vec<MenuItem>,
+    /// The vertical extent of each item, in pixels from the top of the menu.
+    rows: Vec<(u32, u32)>,
+    size: (u32, u32),
+    /// `None` once the menu has been closed.
+    popup: RefCell<Option<(PopupWindow, EventSource)>>,
+    hovered: Cell<Option<usize>>,
+    pixels: RefCell<Vec<u32>>,
+    /// The areas of the menu that changed since it was last presented.
+    dirty: RefCell<Vec<Rect>>,
+}
+
+impl ContextMenu {
+    /// Opens a menu listing `items` at the mouse cursor, above `window`.
+    ///
+    /// Returns `None` if the cursor position can't be determined, or if popups aren't supported on
+    /// this platform, which is currently the case everywhere but Unix.
+    pub fn show(window: &EditorWindow, items: Vec<MenuItem>) -> Option<Self> {
+        let position = window.cursor_position()?;
+        Self::show_at(window, position, items)
+    }
+
+    /// Like `show`, but opens the menu with its top-left at `position` relative to the top-left of
+    /// `window`, e.g. below a button that was activated with the keyboard.
+    ///
+    /// Menus that would extend past the right or bottom of the screen open to the left or above
+    /// `position` instead, and are moved back onto the screen if they still don't fit.
+    pub fn show_at(
+        window: &EditorWindow,
+        position: (i32, i32),
+        items: Vec<MenuItem>,
+    ) -> Option<Self> {
+        let mut top = BORDER + VERTICAL_PADDING;
+        let mut label_width = 0;
+        let rows = items
+            .iter()
+            .map(|item| {
+                let height = match item {
+                    MenuItem::Item { label, .. } => {
+                        label_width = label_width.max(label.chars().count() as u32 * ADVANCE);
+                        ITEM_HEIGHT
+                    }
+                    MenuItem::Separator => SEPARATOR_HEIGHT,
+                };
+                top += height;
+                (top - height, height)
+            })
+            .collect();
+        let size = (
+            MIN_WIDTH.max(label_width + 2 * (BORDER + HORIZONTAL_PADDING)),
+            top + VERTICAL_PADDING + BORDER,
+        );
+
+        let size_xy = (size.0 as i32, size.1 as i32);
+        let position = match (window.screen_position(), window.screen_size()) {
+            (Some(origin), Some(screen_size)) => (
+                fit_on_screen(position.0, size_xy.0, origin.0, screen_size.0),
+                fit_on_screen(position.1, size_xy.1, origin.1, screen_size.1),
+            ),
+            _ => position,
+        };
+        let popup = window.create_popup(position, size_xy)?;
+        let menu = Self {
+            items,
+            rows,
+            size,
+            popup: RefCell::new(Some(popup)),
+            hovered: Cell::new(None),
+            pixels: RefCell::new(vec![0; size.0 as usize * size.1 as usize]),
+            dirty: RefCell::new(vec![]),
+        };
+        menu.dirty.borrow_mut().push(menu.whole());
+        menu.draw();
+        Some(menu)
+    }
+
+    /// Processes the mouse input of the menu, and returns `WindowEvent::MenuItemSelected` once an
+    /// item has been chosen, or `WindowEvent::PopupDismissed` if a mouse button was pressed
+    /// outside of the menu. The menu is closed in both cases.
+    ///
+    /// This should be called along with `EventSource::poll_event` for as long as the menu is
+    /// open, since it also redraws the menu as the cursor moves over it. Returns `None` once the
+    /// menu is closed.
+    pub fn poll_event(&self) -> Option<WindowEvent> {
+        let popup = self.popup.borrow();
+        let (window, events) = popup.as_ref()?;
+        let mut result = None;
+        while let Some(event) = events.poll_event() {
+            match event {
+                WindowEvent::CursorMovement(x, y) => {
+                    let (x, y) = (x * self.size.0 as f32, y * self.size.1 as f32);
+                    self.set_hovered(self.item_at(x, y));
+                }
+                WindowEvent::MouseRelease(_) => {
+                    if let Some(MenuItem::Item { id, .. }) =
+                        self.hovered.get().map(|i| &self.items[i])
+                    {
+                        result = Some(WindowEvent::MenuItemSelected(*id));
+                        break;
+                    }
+                }
+                WindowEvent::PopupDismissed => {
+                    result = Some(WindowEvent::PopupDismissed);
+                    break;
+                }
+                _ => (),
+            }
+        }
+        if result.is_none() {
+            let dirty = std::mem::take(&mut *self.dirty.borrow_mut());
+            window.present_dirty_pixels(&self.pixels.borrow(), self.size.0, self.size.1, &dirty);
+        }
+        drop(popup);
+        if result.is_some() {
+            self.popup.replace(None);
+        }
+        result
+    }
+
+    /// Returns `false` once an item has been chosen or the menu has been dismissed.
+    pub fn is_open(&self) -> bool {
+        self.popup.borrow().is_some()
+    }
+
+    /// Returns the index of the item that can be chosen at the given position within the menu.
+    fn item_at(&self, x: f32, y: f32) -> Option<usize> {
+        if x < 0.0 || x >= self.size.0 as f32 || y < 0.0 {
+            return None;
+        }
+        let y = y as u32;
+        let index = self
+            .rows
+            .iter()
+            .position(|&(top, height)| (top..top + height).contains(&y))?;
+        match self.items[index] {
+            MenuItem::Item { .. } => Some(index),
+            MenuItem::Separator => None,
+        }
+    }
+
+    fn set_hovered(&self, hovered: Option<usize>) {
+        let previous = self.hovered.replace(hovered);
+        if previous == hovered {
+            return;
+        }
+        let rows = previous.into_iter().chain(hovered).map(|index| {
+            let (top, height) = self.rows[index];
+            Rect {
+                x: 0,
+                y: top,
+                width: self.size.0,
+                height,
+            }
+        });
+        self.dirty.borrow_mut().extend(rows);
+        self.draw();
+    }
+
+    fn whole(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width: self.size.0,
+            height: self.size.1,
+        }
+    }
+
+    /// Redraws the whole menu. Only the areas marked as dirty are presented.
+    fn draw(&self) {
+        let mut pixels = self.pixels.borrow_mut();
+        let (width, height) = self.size;
+        fill(&mut pixels, width, self.whole(), BORDER_COLOR);
+        let inner = Rect {
+            x: BORDER,
+            y: BORDER,
+            width: width - 2 * BORDER,
+            height: height - 2 * BORDER,
+        };
+        fill(&mut pixels, width, inner, BACKGROUND_COLOR);
+
+        for (index, (item, &(top, row_height))) in self.items.iter().zip(&self.rows).enumerate() {
+            match item {
+                MenuItem::Item { label, .. } => {
+                    let text_color = if self.hovered.get() == Some(index) {
+                        let row = Rect {
+                            x: BORDER,
+                            y: top,
+                            width: inner.width,
+                            height: row_height,
+                        };
+                        fill(&mut pixels, width, row, HIGHLIGHT_COLOR);
+                        HIGHLIGHTED_TEXT_COLOR
+                    } else {
+                        TEXT_COLOR
+                    };
+                    let text_top = top + (row_height - font::HEIGHT * FONT_SCALE) / 2;
+                    draw_text(
+                        &mut pixels,
+                        width,
+                        (BORDER + HORIZONTAL_PADDING, text_top),
+                        label,
+                        text_color,
+                    );
+                }
+                MenuItem::Separator => {
+                    let line = Rect {
+                        x: BORDER + HORIZONTAL_PADDING / 2,
+                        y: top + row_height / 2,
+                        width: inner.width - HORIZONTAL_PADDING,
+                        height: 1,
+                    };
+                    fill(&mut pixels, width, line, SEPARATOR_COLOR);
+                }
+            }
+        }
+    }
+}
+
+/// Fills `rect` of a buffer of pixels that is `stride` pixels wide.
+fn fill(pixels: &mut [u32], stride: u32, rect: Rect, color: u32) {
+    for y in rect.y..rect.y + rect.height {
+        let start = (y * stride + rect.x) as usize;
+        pixels[start..start + rect.width as usize].fill(color);
+    }
+}
+
+/// Draws `text` with its top-left at `origin`. The menu is sized to fit its labels, so they aren't
+/// clipped.
+fn draw_text(pixels: &mut [u32], stride: u32, origin: (u32, u32), text: &str, color: u32) {
+    for (i, c) in text.chars().enumerate() {
+        let left = origin.0 + i as u32 * ADVANCE;
+        for (column, bits) in font::glyph(c).iter().enumerate() {
+            for row in (0..font::HEIGHT).filter(|row| bits & (1 << row) != 0) {
+                let pixel = Rect {
+                    x: left + column as u32 * FONT_SCALE,
+                    y: origin.1 + row * FONT_SCALE,
+                    width: FONT_SCALE,
+                    height: FONT_SCALE,
+                };
+                fill(pixels, stride, pixel, color);
+            }
+        }
+    }
+}
+
+/// Places a menu of `length` pixels that should start at `position` along one axis of the window,
+/// whose own start is at `origin` on a screen of `screen_length` pixels. The menu is flipped to
+/// end at `position` if it would extend past the end of the screen, then clamped to the screen.
+fn fit_on_screen(position: i32, length: i32, origin: i32, screen_length: i32) -> i32 {
+    let mut start = origin.saturating_add(position);
+    if start.saturating_add(length) > screen_length {
+        start = start.saturating_sub(length);
+    }
+    start = start.min(screen_length - length).max(0);
+    start - origin
+}
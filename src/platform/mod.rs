@@ -3,6 +3,12 @@
 //! Each platform-specific implementation is done within a correspondingly named module (`unix`,
 //! `macos`, `windows`). Each platform module has two submodules - `window` and `event_source`.
 //!
+//! On Unix, the `unix` module is built on the C `xcb` bindings. Enabling the `x11rb` feature
+//! without the `xcb` feature swaps it out for the `x11rb` module, which is built on the pure-Rust
+//! `x11rb` X11 client library instead. The `xcb` backend takes precedence when both are enabled,
+//! so that enabling `x11rb` somewhere in the dependency graph doesn't take the raw window handle
+//! away from other dependents.
+//!
 //! The platform-specific `window` module exposes an `EditorWindowImpl` type that implements
 //! `EditorWindowBackend`.
 //!
@@ -12,28 +18,174 @@
 //! This module contains wrapper code to alias the particular platform-specific module as `os`, and
 //! expose it under more the more restrictive `EditorWindow` and `EventSource` public types.
 
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+#[cfg(not(all(
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ),
+    feature = "x11rb",
+    not(feature = "xcb")
+)))]
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 
 use crate::event::WindowEvent;
 
-#[cfg_attr(any(
+#[cfg_attr(
+    all(
+        any(
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        ),
+        any(feature = "xcb", not(feature = "x11rb"))
+    ),
+    path = "unix/mod.rs"
+)]
+#[cfg_attr(
+    all(
+        any(
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        ),
+        feature = "x11rb",
+        not(feature = "xcb")
+    ),
+    path = "x11rb/mod.rs"
+)]
+#[cfg_attr(target_os = "macos", path = "macos/mod.rs")]
+#[cfg_attr(target_os = "windows", path = "windows/mod.rs")]
+mod os;
+
+#[cfg(all(
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ),
+    not(any(feature = "xcb", feature = "x11rb"))
+))]
+compile_error!("either the `xcb` or the `x11rb` feature must be enabled on Unix platforms");
+
+#[cfg(any(
     target_os = "linux",
     target_os = "dragonfly",
     target_os = "freebsd",
     target_os = "netbsd",
     target_os = "openbsd"
-), path = "unix/mod.rs")]
-#[cfg_attr(target_os = "macos", path = "macos/mod.rs")]
-#[cfg_attr(target_os = "windows", path = "windows/mod.rs")]
-mod os;
+))]
+mod x11;
+
+mod motion;
 
+use motion::MotionCoalescer;
 use os::event_source::EventSourceImpl;
 use os::window::EditorWindowImpl;
 
 /// Crate-internal cross-platform window handle creation API required on each platform.
-trait EditorWindowBackend: raw_window_handle::HasRawWindowHandle {
+///
+/// Backends also implement `HasRawWindowHandle`, except for the `x11rb` backend, which has no
+/// `xcb_connection_t` to put in the handle.
+trait EditorWindowBackend {
     /// Builds a platform-specific window, using a provided window handle as a parent window.
     fn build(parent: *mut std::os::raw::c_void, size_xy: (i32, i32)) -> Self;
+    /// Draws the `dirty` rectangles of a `width` x `height` buffer of `0x00RRGGBB` pixels to the
+    /// window, which is aligned with the top-left of the buffer.
+    ///
+    /// Platforms without software presentation support ignore the pixels.
+    fn present_pixels(&self, _pixels: &[u32], _width: u32, _height: u32, _dirty: &[Rect]) {}
+    /// Returns the mechanism used by `present_pixels`.
+    fn present_method(&self) -> PresentMethod {
+        PresentMethod::Unsupported
+    }
+    /// Reads back the current contents of the window.
+    ///
+    /// Platforms without capture support return `None`.
+    fn capture(&self) -> Option<Image> {
+        None
+    }
+    /// Returns the text on the system clipboard, if there is any.
+    ///
+    /// Platforms without clipboard support return `None`.
+    fn clipboard_text(&self) -> Option<String> {
+        None
+    }
+    /// Replaces the contents of the system clipboard with `text`.
+    ///
+    /// Platforms without clipboard support ignore the text.
+    fn set_clipboard_text(&self, _text: &str) {}
+    /// Moves the spot near which the input method places its candidate window, in pixels
+    /// relative to the top-left of the window.
+    ///
+    /// Platforms without input method support ignore the position.
+    fn set_ime_position(&self, _x: i32, _y: i32) {}
+    /// Returns the position of the mouse cursor in pixels relative to the top-left of the window,
+    /// even if it is outside of the window.
+    ///
+    /// Platforms without cursor position support return `None`.
+    fn cursor_position(&self) -> Option<(i32, i32)> {
+        None
+    }
+    /// Returns the position of the window's top-left relative to its parent's, in pixels.
+    ///
+    /// Platforms without window positioning support return `None`.
+    fn position(&self) -> Option<(i32, i32)> {
+        None
+    }
+    /// Moves the window to `x` and `y` pixels from its parent's top-left.
+    ///
+    /// Platforms without window positioning support ignore the position.
+    fn set_position(&self, _x: i32, _y: i32) {}
+    /// Returns the position of the window's top-left relative to the screen's, in pixels.
+    ///
+    /// Platforms without window positioning support return `None`.
+    fn screen_position(&self) -> Option<(i32, i32)> {
+        None
+    }
+    /// Returns the size of the screen containing the window, in pixels.
+    ///
+    /// Platforms without window positioning support return `None`.
+    fn screen_size(&self) -> Option<(i32, i32)> {
+        None
+    }
+    /// Returns the X11 id of the window.
+    ///
+    /// Platforms other than X11 return `None`.
+    fn x11_window_id(&self) -> Option<u32> {
+        None
+    }
+    /// Captures the pointer, so that the window keeps receiving motion and button events while
+    /// the cursor is outside of it. Returns whether the pointer could be captured.
+    ///
+    /// Platforms without pointer capture support return `false`.
+    fn grab_pointer(&self) -> bool {
+        false
+    }
+    /// Releases a capture made with `grab_pointer`.
+    fn ungrab_pointer(&self) {}
+    /// Builds a popup window of size `size_xy` above all other windows, at `position` relative to
+    /// the top-left of this window.
+    ///
+    /// Platforms without popup support return `None`.
+    fn create_popup(&self, _position: (i32, i32), _size_xy: (i32, i32)) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
 
 /// Crate-internal cross-platform event source API required on each platform.
@@ -42,8 +194,57 @@ trait EventSourceBackend {
     fn new(window: &EditorWindowImpl, size_xy: (i32, i32)) -> Self;
     /// Returns the next `WindowEvent`, if one is available.
     fn poll_event(&self) -> Option<WindowEvent>;
+    /// Starts emitting `WindowEvent::Frame` every `interval`, or stops if `interval` is `None`.
+    ///
+    /// Platforms without frame timing support never emit `Frame` events.
+    fn set_frame_interval(&self, _interval: Option<std::time::Duration>) {}
+    /// Returns a function that can be called from any thread to wake up anything waiting for
+    /// events from this event source.
+    ///
+    /// Platforms that can't be woken up return `None`.
+    fn wake_fn(&self) -> Option<WakeFn> {
+        None
+    }
+    /// Returns how long until `poll_event` has something to report even if no new events arrive,
+    /// such as a timeout expiring.
+    ///
+    /// Platforms without such timeouts return `None`.
+    fn poll_timeout(&self) -> Option<std::time::Duration> {
+        None
+    }
+    /// Blocks until new events may be available, or until `timeout` has elapsed. Returning early
+    /// is allowed, since the caller polls again afterwards.
+    ///
+    /// Platforms that can't block on their event queue sleep briefly instead.
+    fn wait(&self, timeout: Option<std::time::Duration>) {
+        let step = std::time::Duration::from_millis(10);
+        std::thread::sleep(timeout.map_or(step, |timeout| timeout.min(step)));
+    }
+    /// Starts or stops accepting files dragged onto the window.
+    ///
+    /// Platforms without drag and drop support never emit file events.
+    fn set_file_drop_enabled(&self, _enabled: bool) {}
+    /// Accepts or rejects the files being dragged at the most recently reported position.
+    fn set_drop_accepted(&self, _accepted: bool) {}
+    /// Starts dragging `data` out of the window, returning whether the drag could be started.
+    ///
+    /// Platforms without drag and drop support return `false`.
+    fn start_drag(&self, _data: DragData) -> bool {
+        false
+    }
+    /// Starts or stops capturing the pointer while mouse buttons are held within the window.
+    ///
+    /// Platforms that always capture the pointer, or can't capture it, ignore the setting.
+    fn set_capture_on_press(&self, _capture: bool) {}
+    /// Starts or stops following the XEmbed protocol.
+    ///
+    /// Platforms other than X11 ignore the setting.
+    fn set_xembed_enabled(&self, _enabled: bool) {}
 }
 
+/// Wakes up an event source from another thread. Returned by `EventSourceBackend::wake_fn`.
+type WakeFn = Arc<dyn Fn() + Send + Sync>;
+
 /// Build a platform-specific window and return a cross-platform `RawWindowHandle` implementor,
 /// used as a surface for rendering, as well as a cross-platform `EventSource`, which is used to
 /// poll `WindowEvent`s.
@@ -54,8 +255,51 @@ pub fn setup(
     size_xy: (i32, i32),
 ) -> (EditorWindow, EventSource) {
     let window = EditorWindowImpl::build(parent, size_xy);
-    let event_source = EventSourceImpl::new(&window, size_xy);
-    (EditorWindow(window), EventSource(event_source))
+    let event_source = EventSource::new(&window, size_xy);
+    (EditorWindow(window), event_source)
+}
+
+/// The mechanism used by `EditorWindow::present_pixels` to transfer pixels to the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentMethod {
+    /// Pixels are written to memory shared with the display server, which then copies them
+    /// directly to the window. Used on X11 when the MIT-SHM extension is available and the X
+    /// server is running on the same machine.
+    SharedMemory,
+    /// Pixels are sent to the display server over its connection. Used on X11 as a fallback when
+    /// shared memory can't be used.
+    PutImage,
+    /// Software presentation isn't supported on this platform.
+    Unsupported,
+}
+
+/// A rectangular area of a window or pixel buffer, in pixels from its top-left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// An RGBA image captured from a window by `EditorWindow::capture`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// `width * height` pixels in row-major order, each stored as 4 bytes: red, green, blue and
+    /// alpha. Windows are opaque, so alpha is always 255.
+    pub data: Vec<u8>,
+}
+
+/// Data dragged out of the editor with `EventSource::start_drag`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DragData {
+    /// Local files, such as a rendered audio clip. On X11, these are offered as a `text/uri-list`.
+    /// Relative paths are resolved against the current directory when the drag starts.
+    Files(Vec<PathBuf>),
+    /// Arbitrary data of the given MIME type, such as `text/plain;charset=utf-8`.
+    Mime { mime_type: String, data: Vec<u8> },
 }
 
 /// `RawWindowHandle` implementor returned by the `setup` function.
@@ -63,19 +307,521 @@ pub struct EditorWindow(EditorWindowImpl);
 
 /// The `EditorWindow` can be passed to any rendering backend that accepts raw window handles
 /// through the `raw-window-handle` crate.
+///
+/// This isn't implemented by the `x11rb` backend, used when the `x11rb` feature is enabled without
+/// the `xcb` feature, since `x11rb` doesn't use libxcb and so has no `xcb_connection_t` to hand
+/// out. Renderers must then open their own connection to the X server, and draw to the window with
+/// the id returned by `EditorWindow::x11_window_id`.
+#[cfg(not(all(
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ),
+    feature = "x11rb",
+    not(feature = "xcb")
+)))]
 unsafe impl HasRawWindowHandle for EditorWindow {
     fn raw_window_handle(&self) -> RawWindowHandle {
         self.0.raw_window_handle()
     }
 }
 
+impl EditorWindow {
+    /// Draws a CPU-rendered frame to the window, for editors that don't use a GPU renderer.
+    ///
+    /// `pixels` holds `width * height` pixels in row-major order, each encoded as `0x00RRGGBB`.
+    /// The frame is drawn starting from the top-left corner of the window, and is clipped to the
+    /// window's bounds.
+    ///
+    /// This is currently only supported on Unix; other platforms ignore the pixels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pixels` doesn't contain exactly `width * height` pixels.
+    pub fn present_pixels(&self, pixels: &[u32], width: u32, height: u32) {
+        let frame = Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        self.present_dirty_pixels(pixels, width, height, &[frame])
+    }
+
+    /// Like `present_pixels`, but only uploads the `dirty` rectangles of the frame, for editors
+    /// that track which parts of their UI changed since the last frame.
+    ///
+    /// Any areas of the window that the display server has asked to be redrawn since the last
+    /// frame (e.g. after being uncovered) are uploaded as well, so the rest of the frame must still
+    /// hold up-to-date pixels. On X11, these exposures are collected while polling the
+    /// `EventSource`. Rectangles are clipped to the frame's bounds.
+    ///
+    /// This is currently only supported on Unix; other platforms ignore the pixels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pixels` doesn't contain exactly `width * height` pixels.
+    pub fn present_dirty_pixels(&self, pixels: &[u32], width: u32, height: u32, dirty: &[Rect]) {
+        assert_eq!(
+            pixels.len(),
+            width as usize * height as usize,
+            "pixel buffer does not match the given dimensions"
+        );
+        self.0.present_pixels(pixels, width, height, dirty)
+    }
+
+    /// Returns the mechanism used by `present_pixels` to transfer pixels to the window.
+    ///
+    /// On X11, the MIT-SHM extension is preferred when available, falling back to sending pixels
+    /// over the X server connection otherwise.
+    pub fn present_method(&self) -> PresentMethod {
+        self.0.present_method()
+    }
+
+    /// Reads back the current contents of the window, e.g. to compare an editor's rendering
+    /// against reference images in tests.
+    ///
+    /// Returns `None` if the window's contents can't be read, e.g. because it isn't mapped yet.
+    /// Areas of the window that are obscured by other windows may hold undefined contents.
+    ///
+    /// This is currently only supported on Unix, for 24 and 32 bit deep TrueColor visuals. Other
+    /// platforms always return `None`.
+    pub fn capture(&self) -> Option<Image> {
+        self.0.capture()
+    }
+
+    /// Returns a handle to the system clipboard, e.g. for copying parameter values and preset
+    /// names between plugin instances.
+    pub fn clipboard(&self) -> Clipboard<'_> {
+        Clipboard(&self.0)
+    }
+
+    /// Tells the input method where text is being entered, in pixels relative to the top-left of
+    /// the window, so that its candidate window can be placed next to it. This is usually the
+    /// bottom-left of the text cursor, and should be updated whenever the cursor moves.
+    ///
+    /// Composed text is received as `WindowEvent::ImePreedit` and `WindowEvent::ImeCommit`.
+    ///
+    /// This is currently only supported on Unix, through XIM, when the `ime` feature is enabled.
+    /// Other platforms ignore the position.
+    pub fn set_ime_position(&self, x: i32, y: i32) {
+        self.0.set_ime_position(x, y)
+    }
+
+    /// Returns the position of the editor's window relative to the host's parent window, in
+    /// pixels from the parent's top-left. Windows are placed at (0, 0) by `setup`.
+    ///
+    /// This is currently only supported on Unix; other platforms always return `None`.
+    pub fn position(&self) -> Option<(i32, i32)> {
+        self.0.position()
+    }
+
+    /// Moves the editor's window within the host's parent window, to `x` and `y` pixels from the
+    /// parent's top-left, e.g. to center it within a parent that is larger than the editor.
+    ///
+    /// This is currently only supported on Unix; other platforms ignore the position.
+    pub fn set_position(&self, x: i32, y: i32) {
+        self.0.set_position(x, y)
+    }
+
+    /// Returns the position of the editor's window on the screen, in pixels from the screen's
+    /// top-left, e.g. for placing feedback windows during drags. Positions within the window can
+    /// be converted to screen positions by adding them to it.
+    ///
+    /// Popups are already placed relative to the window with `create_popup`.
+    ///
+    /// This is currently only supported on Unix; other platforms always return `None`.
+    pub fn screen_position(&self) -> Option<(i32, i32)> {
+        self.0.screen_position()
+    }
+
+    /// Returns the size of the screen the editor's window is on, in pixels, e.g. for keeping
+    /// popups opened with `create_popup` within the screen.
+    ///
+    /// This is currently only supported on Unix; other platforms always return `None`.
+    pub fn screen_size(&self) -> Option<(i32, i32)> {
+        self.0.screen_size()
+    }
+
+    /// Returns the X11 id of the editor's window, e.g. for renderers that open their own
+    /// connection to the X server when the window has no raw window handle, as with the `x11rb`
+    /// backend.
+    ///
+    /// Platforms other than X11 return `None`.
+    pub fn x11_window_id(&self) -> Option<u32> {
+        self.0.x11_window_id()
+    }
+
+    /// Captures the pointer, so that the editor keeps receiving `CursorMovement` and button events
+    /// while the cursor is outside of its window, e.g. while dragging a knob past the window's
+    /// edge. Returns whether the pointer could be captured, which fails if another application
+    /// holds a capture. The capture lasts until `ungrab_pointer` is called.
+    ///
+    /// Events over the window are reported as usual while capturing. To only capture the pointer
+    /// while a button is held down, use `EventSource::set_capture_on_press` instead.
+    ///
+    /// This is currently only supported on Unix; other platforms always return `false`.
+    pub fn grab_pointer(&self) -> bool {
+        self.0.grab_pointer()
+    }
+
+    /// Releases a capture made with `grab_pointer`, or with capture-on-press while a button is
+    /// held down.
+    pub fn ungrab_pointer(&self) {
+        self.0.ungrab_pointer()
+    }
+
+    /// Opens a popup window of size `size_xy` at `position` relative to the top-left of this
+    /// window, e.g. for a context menu or tooltip. Unlike the editor's window, popups are placed
+    /// above all other windows, so they aren't clipped to the editor's bounds.
+    ///
+    /// The popup has its own `EventSource`, which emits `WindowEvent::PopupDismissed` when a mouse
+    /// button is pressed outside of it. The popup is closed by dropping it. Popups don't connect to
+    /// input methods, so they never emit composition events.
+    ///
+    /// This is currently only supported on Unix, where the popup grabs the pointer while it is
+    /// open. Other platforms return `None`.
+    pub fn create_popup(
+        &self,
+        position: (i32, i32),
+        size_xy: (i32, i32),
+    ) -> Option<(PopupWindow, EventSource)> {
+        let window = self.0.create_popup(position, size_xy)?;
+        let event_source = EventSource::new(&window, size_xy);
+        Some((PopupWindow(EditorWindow(window)), event_source))
+    }
+
+    /// Returns the position of the mouse cursor in pixels relative to the top-left of the window,
+    /// or `None` if it can't be determined.
+    pub(crate) fn cursor_position(&self) -> Option<(i32, i32)> {
+        self.0.cursor_position()
+    }
+}
+
+/// A popup window opened with `EditorWindow::create_popup`. Like the editor's window, it can be
+/// rendered to through its raw window handle or with `present_pixels`, and it dereferences to an
+/// `EditorWindow` for access to those methods.
+pub struct PopupWindow(EditorWindow);
+
+#[cfg(not(all(
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ),
+    feature = "x11rb",
+    not(feature = "xcb")
+)))]
+unsafe impl HasRawWindowHandle for PopupWindow {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        self.0.raw_window_handle()
+    }
+}
+
+impl std::ops::Deref for PopupWindow {
+    type Target = EditorWindow;
+
+    fn deref(&self) -> &EditorWindow {
+        &self.0
+    }
+}
+
+/// The system clipboard, accessed through `EditorWindow::clipboard`.
+///
+/// On X11, this is the `CLIPBOARD` selection. Setting text makes the editor window its owner, and
+/// other clients' requests for the text are answered while polling the `EventSource`, so the
+/// event source must keep being polled for pasting into other applications to work. The text is
+/// offered until another client takes ownership of the clipboard.
+///
+/// This is currently only supported on Unix; on other platforms the clipboard always appears
+/// empty.
+pub struct Clipboard<'a>(&'a EditorWindowImpl);
+
+impl Clipboard<'_> {
+    /// Returns the text on the clipboard, or `None` if the clipboard is empty or doesn't hold
+    /// text.
+    ///
+    /// On X11, this blocks until the application owning the clipboard has provided the text, for
+    /// up to a second. Very large text, which X11 clients transfer incrementally, isn't supported.
+    pub fn get_text(&self) -> Option<String> {
+        self.0.clipboard_text()
+    }
+
+    /// Places `text` on the clipboard, replacing its previous contents.
+    pub fn set_text(&self, text: &str) {
+        self.0.set_clipboard_text(text)
+    }
+}
+
 /// Source of events from a corresponding window, created by the `setup` function.
-pub struct EventSource(EventSourceImpl);
+pub struct EventSource {
+    backend: EventSourceImpl,
+    /// Payloads sent through a `Waker`, which are returned before any window events.
+    user_events: Arc<Mutex<VecDeque<u64>>>,
+    motion: Mutex<MotionCoalescer>,
+}
 
 impl EventSource {
+    fn new(window: &EditorWindowImpl, size_xy: (i32, i32)) -> Self {
+        Self {
+            backend: EventSourceImpl::new(window, size_xy),
+            user_events: Arc::default(),
+            motion: Mutex::default(),
+        }
+    }
+
     /// Returns the next `WindowEvent`, if one is available. This should be called in a `while let`
     /// loop until empty.
     pub fn poll_event(&self) -> Option<WindowEvent> {
-        self.0.poll_event()
+        self.motion.lock().unwrap().next(|| {
+            if let Some(payload) = self.user_events.lock().unwrap().pop_front() {
+                return Some(WindowEvent::User(payload));
+            }
+            self.backend.poll_event()
+        })
+    }
+
+    /// Enables or disables motion coalescing, which is disabled by default.
+    ///
+    /// The X server can report hundreds of cursor movements per frame. While coalescing, each run
+    /// of consecutive `CursorMovement` events that are already available is collapsed into a
+    /// single one with the latest position, so that editors doing heavy work per event don't fall
+    /// behind. Other events, such as clicks and releases, are still returned in order.
+    pub fn set_coalesce_motion(&self, coalesce: bool) {
+        self.motion.lock().unwrap().enabled = coalesce;
+    }
+
+    /// Returns every position that was merged into the most recent `CursorMovement` returned while
+    /// coalescing, oldest first. The last entry is the reported position.
+    ///
+    /// This can be used by editors that need the full path of the cursor, e.g. for drawing.
+    pub fn coalesced_motion(&self) -> Vec<(f32, f32)> {
+        self.motion.lock().unwrap().history().to_vec()
+    }
+
+    /// Blocks until the next `WindowEvent` is available, and returns it. This is intended for
+    /// dedicated UI threads and standalone use, rather than for `Editor::idle`.
+    ///
+    /// On X11, this waits on the X server connection with `poll(2)` rather than spinning. Other
+    /// platforms currently poll for events at a short interval.
+    pub fn wait_event(&self) -> WindowEvent {
+        loop {
+            if let Some(event) = self.poll_event() {
+                return event;
+            }
+            self.backend.wait(None);
+        }
+    }
+
+    /// Like `wait_event`, but gives up and returns `None` if no event is available within
+    /// `timeout`.
+    pub fn wait_event_timeout(&self, timeout: std::time::Duration) -> Option<WindowEvent> {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.poll_event() {
+                return Some(event);
+            }
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                return None;
+            }
+            self.backend.wait(Some(remaining));
+        }
+    }
+
+    /// Returns how soon the event source has to be polled again, even if its file descriptor
+    /// doesn't become readable, or `None` if it only needs to be polled once it does. This is only
+    /// needed when driving the event source from an external event loop; `wait_event`,
+    /// `wait_event_timeout` and `EventStream` already take it into account.
+    ///
+    /// On X11, this is the time left before the target of a drag started with `start_drag` is
+    /// given up on for not answering.
+    pub fn poll_timeout(&self) -> Option<std::time::Duration> {
+        self.backend.poll_timeout()
+    }
+
+    /// Allows files to be dragged and dropped onto the window, which is disabled by default.
+    ///
+    /// While files are dragged over the window, `FileHovered` events report the position of the
+    /// cursor. A drop then results in `FileDropped`, or `FileHoverCancelled` if the files leave
+    /// the window or the drop fails.
+    ///
+    /// On X11, this implements the target side of the XDND protocol. Drag sources only offer
+    /// drops to windows that advertise support for it, so some hosts may not forward drops to
+    /// the editor's child window. This is currently only supported on Unix; other platforms never
+    /// emit file events.
+    pub fn set_file_drop_enabled(&self, enabled: bool) {
+        self.backend.set_file_drop_enabled(enabled)
+    }
+
+    /// Accepts or rejects a drop at the position reported by the latest `FileHovered` event.
+    /// Drops are accepted by default, and the last decision carries over to later positions.
+    ///
+    /// To decide per position, this should be called while handling each `FileHovered` event,
+    /// before polling for the next event.
+    pub fn set_drop_accepted(&self, accepted: bool) {
+        self.backend.set_drop_accepted(accepted)
+    }
+
+    /// Starts dragging `data` out of the window, e.g. to drop a rendered clip onto the host's
+    /// timeline. This should be called while handling a `MouseClick`, with the button still held
+    /// down.
+    ///
+    /// The drag follows the pointer until the button is released, and ends with a
+    /// `WindowEvent::DragEnded` event. `CursorMovement` and `MouseRelease` events are still emitted
+    /// in the meantime. Returns `false` if the drag couldn't be started, e.g. because another drag
+    /// is already in progress, or its drop is still being handled by the target.
+    ///
+    /// On X11, this implements the source side of the XDND protocol, and the pointer is grabbed
+    /// for the duration of the drag. The event source must keep being polled until the drag has
+    /// ended, since the drop target's messages and requests for the data are handled while
+    /// polling. Targets that don't answer within two seconds of the button's release are given up
+    /// on, ending the drag as rejected. This is currently only supported on Unix; other platforms
+    /// always return `false`.
+    pub fn start_drag(&self, data: DragData) -> bool {
+        self.backend.start_drag(data)
+    }
+
+    /// Enables or disables capture-on-press, which is disabled by default.
+    ///
+    /// While enabled, the pointer is captured when a mouse button is pressed within the window,
+    /// and released once all buttons are released, so that drags which leave the window keep
+    /// reporting `CursorMovement` events and the final `MouseRelease`. Coordinates outside of the
+    /// window are outside of the [0, 1] range.
+    ///
+    /// On X11, the pointer is grabbed with owner events. On Windows, the pointer is always
+    /// captured while a button is held down, regardless of this setting. This is currently not
+    /// supported on macOS.
+    pub fn set_capture_on_press(&self, capture: bool) {
+        self.backend.set_capture_on_press(capture)
+    }
+
+    /// Enables or disables the XEmbed protocol, which is disabled by default. This should be
+    /// enabled right after `setup` for hosts that embed plugin UIs through XEmbed, such as LV2
+    /// hosts using a `GtkSocket`.
+    ///
+    /// The window always advertises XEmbed support with its `_XEMBED_INFO` property, which
+    /// embedders read before it is mapped. While enabled, `WindowEvent::Focused` is emitted when
+    /// the embedder hands the keyboard focus to or away from the editor. Key events are then
+    /// forwarded by the embedder. Clicking the editor asks the embedder for the focus.
+    ///
+    /// This only applies to X11; other platforms ignore the setting.
+    pub fn set_xembed_enabled(&self, enabled: bool) {
+        self.backend.set_xembed_enabled(enabled)
+    }
+
+    /// Returns a handle that other threads, such as the audio thread, can use to send
+    /// `WindowEvent::User` events to this event source.
+    ///
+    /// On X11, this opens a separate connection to the X server for the `Waker`, so it should be
+    /// called on the editor thread rather than on the thread that will be waking it up.
+    pub fn waker(&self) -> Waker {
+        Waker {
+            user_events: Arc::clone(&self.user_events),
+            wake: self.backend.wake_fn(),
+        }
+    }
+
+    /// Starts emitting `WindowEvent::Frame` events at the given rate, in frames per second, so
+    /// that animations don't depend on how often the host calls `Editor::idle`. Passing `None`
+    /// stops the frame events.
+    ///
+    /// Frames are paced by a timer thread, which wakes the event queue. If events are polled less
+    /// often than the requested rate, frames are skipped rather than queued up, so at most one
+    /// `Frame` is returned per poll.
+    ///
+    /// This is currently only supported on Unix; other platforms never emit `Frame` events.
+    ///
+    /// Rates so low that the interval between frames can't be represented never emit a frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frames_per_second` is not positive and finite.
+    pub fn set_frame_rate(&self, frames_per_second: Option<f64>) {
+        let interval = frames_per_second.map(|rate| {
+            assert!(
+                rate.is_finite() && rate > 0.,
+                "frame rate must be positive and finite"
+            );
+            std::time::Duration::try_from_secs_f64(1. / rate).unwrap_or(std::time::Duration::MAX)
+        });
+        self.backend.set_frame_interval(interval)
+    }
+}
+
+/// A `Send + Sync` handle for sending `WindowEvent::User` events to an `EventSource` from other
+/// threads, created by `EventSource::waker`.
+#[derive(Clone)]
+pub struct Waker {
+    user_events: Arc<Mutex<VecDeque<u64>>>,
+    wake: Option<WakeFn>,
+}
+
+impl Waker {
+    /// Queues a `WindowEvent::User` event with the given payload, e.g. to notify the editor that
+    /// new meter values are available.
+    ///
+    /// On X11, a `ClientMessage` is also sent to the window, so that anything waiting on the X
+    /// server connection wakes up immediately. Other platforms return the event from the next
+    /// `poll_event` call.
+    ///
+    /// This never waits for the editor thread, but it isn't free of blocking either: it briefly
+    /// takes a lock shared with the event source, and on X11 it writes the message to a socket
+    /// connected to the X server, which blocks if the socket's buffer is full. Audio threads that
+    /// can't tolerate system calls should hand payloads to another thread to send instead.
+    pub fn wake(&self, payload: u64) {
+        self.user_events.lock().unwrap().push_back(payload);
+        if let Some(wake) = &self.wake {
+            wake();
+        }
+    }
+}
+
+/// The event source can be registered with an external event loop, such as `calloop`, `mio` or
+/// `tokio`, to drive the editor without relying on the host's idle callbacks.
+///
+/// Whenever the file descriptor becomes readable, `poll_event` should be called until it returns
+/// `None`, since events may already have been read from it into the X11 client library's queue.
+/// If the `EditorWindow` is used from another thread, events may also be read into that queue
+/// without the file descriptor becoming readable, so the event source should additionally be
+/// polled at a low rate in that case. The same applies to messages from the input method when the
+/// `ime` feature is enabled, since it is spoken to over a separate connection.
+///
+/// Some events are only produced by polling, such as the `DragEnded` event of a drag whose target
+/// stopped answering. Whenever `EventSource::poll_timeout` returns a duration after polling, the
+/// event source must be polled again within it, e.g. by arming a timer in the event loop.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+impl std::os::unix::io::AsRawFd for EventSource {
+    /// Returns the file descriptor of the connection to the X server.
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        self.backend.as_raw_fd()
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+impl std::os::unix::io::AsFd for EventSource {
+    /// Borrows the file descriptor of the connection to the X server. See the `AsRawFd`
+    /// implementation for how to use it.
+    fn as_fd(&self) -> std::os::unix::io::BorrowedFd<'_> {
+        use std::os::unix::io::AsRawFd;
+        // The connection stays open at least as long as the `EventSource`.
+        unsafe { std::os::unix::io::BorrowedFd::borrow_raw(self.as_raw_fd()) }
     }
 }
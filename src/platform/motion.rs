@@ -0,0 +1,50 @@
+//! Coalescing of consecutive cursor movements, shared by all platforms.
+
+use crate::event::WindowEvent;
+
+/// Collapses runs of consecutive `CursorMovement` events into the latest one, keeping the
+/// positions that were merged so they can be inspected afterwards.
+#[derive(Default)]
+pub struct MotionCoalescer {
+    pub enabled: bool,
+    /// An event that was polled while looking for the end of a run of movements, to be returned
+    /// next.
+    pending: Option<WindowEvent>,
+    /// Every position merged into the most recently returned `CursorMovement`, oldest first.
+    history: Vec<(f32, f32)>,
+}
+
+impl MotionCoalescer {
+    /// Returns the next event from `poll`, merging any directly following movements into it if
+    /// coalescing is enabled. Other events are never reordered.
+    pub fn next(&mut self, mut poll: impl FnMut() -> Option<WindowEvent>) -> Option<WindowEvent> {
+        let event = match self.pending.take() {
+            Some(event) => event,
+            None => poll()?,
+        };
+        let mut latest = match (self.enabled, event) {
+            (true, WindowEvent::CursorMovement(x, y)) => (x, y),
+            (_, event) => return Some(event),
+        };
+        self.history.clear();
+        self.history.push(latest);
+        loop {
+            match poll() {
+                Some(WindowEvent::CursorMovement(x, y)) => {
+                    latest = (x, y);
+                    self.history.push(latest);
+                }
+                other => {
+                    self.pending = other;
+                    break;
+                }
+            }
+        }
+        Some(WindowEvent::CursorMovement(latest.0, latest.1))
+    }
+
+    /// Returns every position merged into the most recently coalesced `CursorMovement`.
+    pub fn history(&self) -> &[(f32, f32)] {
+        &self.history
+    }
+}
@@ -1,61 +1,323 @@
 //! Provides a source for window events on Unix platforms.
 
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
 use super::window::EditorWindowImpl;
 use crate::event::WindowEvent;
-use crate::platform::EventSourceBackend;
+use crate::platform::x11::atoms::Atoms;
+use crate::platform::x11::capture::PointerCapture;
+use crate::platform::x11::connection::X11Connection;
+use crate::platform::x11::dnd::{DragSource, DropTarget, XdndConnection};
+use crate::platform::x11::frame::FrameTimer;
+use crate::platform::x11::image::Exposures;
+#[cfg(feature = "ime")]
+use crate::platform::x11::ime::InputMethod;
+use crate::platform::x11::keyboard::HeldKeys;
+use crate::platform::x11::popup::PopupGrab;
+use crate::platform::x11::selection::{
+    answer_request, SelectionConnection, SelectionRequest, Selections,
+};
+#[cfg(feature = "ime")]
+use crate::platform::x11::wait::wait_until_any_readable;
+use crate::platform::x11::wait::wait_until_readable;
+use crate::platform::x11::xembed::XembedClient;
+use crate::platform::{DragData, EventSourceBackend, Rect, WakeFn};
 
 pub(in crate::platform) struct EventSourceImpl {
-    connection: xcb::base::Connection,
+    connection: Arc<xcb::base::Connection>,
     size_xy: (i32, i32),
+    exposures: Exposures,
+    window_id: u32,
+    root_id: u32,
+    atoms: Atoms,
+    frame_timer: Mutex<Option<FrameTimer>>,
+    drop_target: DropTarget,
+    drag_source: DragSource,
+    selections: Selections,
+    keys: HeldKeys,
+    capture: PointerCapture,
+    xembed: XembedClient,
+    /// Set if the window is a popup.
+    popup: Option<PopupGrab>,
+    #[cfg(feature = "ime")]
+    ime: InputMethod,
 }
 
 impl EventSourceBackend for EventSourceImpl {
     fn new(window: &EditorWindowImpl, size_xy: (i32, i32)) -> Self {
-        let connection = unsafe {
-            xcb::base::Connection::from_raw_conn(window.connection.as_ref().unwrap().get_raw_conn())
-        };
-
+        let atoms = window.atoms;
         Self {
-            connection,
+            connection: Arc::clone(&window.connection),
             size_xy,
+            exposures: window.exposures.clone(),
+            window_id: window.window_id,
+            root_id: window.root_id,
+            atoms,
+            frame_timer: Mutex::new(None),
+            drop_target: DropTarget::new(atoms, window.window_id, size_xy),
+            drag_source: DragSource::new(
+                atoms,
+                window.window_id,
+                window.root_id,
+                window.selections.clone(),
+            ),
+            selections: window.selections.clone(),
+            keys: HeldKeys::default(),
+            capture: PointerCapture::new(window.window_id),
+            xembed: XembedClient::new(atoms),
+            popup: window
+                .popup
+                .then(|| PopupGrab::new(window.window_id, size_xy)),
+            #[cfg(feature = "ime")]
+            ime: window.ime.clone(),
         }
     }
 
     /// The XCB API for getting window events is essentially identical to `vst_window`'s event
     /// polling API.
+    ///
+    /// XCB events that have no `WindowEvent` equivalent are skipped, so that `None` is only
+    /// returned once the queue is actually empty. Exposures are passed on to the window, to be
+    /// redrawn by the next `present_pixels` call, and requests for the contents of the window's
+    /// selections are answered along the way. If an input method is in use, its composition
+    /// events are returned first, and key presses are forwarded to it.
     fn poll_event(&self) -> Option<WindowEvent> {
-        let xcb_event = self.connection.poll_for_event();
-        match xcb_event {
-            None => None,
-            Some(xcb_event) => {
-                let r = xcb_event.response_type() & !0x80;
-                match r {
-                    xcb::MOTION_NOTIFY => {
-                        let motion: &xcb::MotionNotifyEvent =
-                            unsafe { xcb::cast_event(&xcb_event) };
-                        Some(WindowEvent::CursorMovement(
-                            motion.event_x() as f32 / self.size_xy.0 as f32,
-                            motion.event_y() as f32 / self.size_xy.1 as f32,
-                        ))
-                    }
-                    xcb::BUTTON_PRESS => {
-                        let button: &xcb::ButtonPressEvent = unsafe { xcb::cast_event(&xcb_event) };
-                        convert_mouse_button_detail(button.detail()).map(WindowEvent::MouseClick)
-                    }
-                    xcb::BUTTON_RELEASE => {
-                        let button: &xcb::ButtonReleaseEvent =
-                            unsafe { xcb::cast_event(&xcb_event) };
-                        convert_mouse_button_detail(button.detail()).map(WindowEvent::MouseRelease)
-                    }
-                    _ => {
-                        None
-                    }
+        self.drop_target.flush_status(self);
+        if let Some(popup) = &self.popup {
+            popup.ensure_grabbed(self);
+        }
+        if let Some(event) = self.drag_source.take_ended_event(self) {
+            return Some(event);
+        }
+        #[cfg(feature = "ime")]
+        if let Some(event) = self.ime.poll_event(&self.keys) {
+            return Some(event);
+        }
+        loop {
+            let xcb_event = self.connection.poll_for_event()?;
+            if let Some(event) = self.convert_event(&xcb_event) {
+                return Some(event);
+            }
+        }
+    }
+
+    /// The timer thread sends a `ClientMessage` to the window, which is converted into a `Frame`.
+    fn set_frame_interval(&self, interval: Option<Duration>) {
+        let mut frame_timer = self.frame_timer.lock().unwrap();
+        // The previous timer is stopped before the next one starts.
+        *frame_timer = None;
+        *frame_timer = interval.map(|interval| {
+            let sender = ClientMessageSender::new(self.window_id, self.atoms.frame);
+            FrameTimer::start(interval, move || sender.send())
+        });
+    }
+
+    /// Waits for the X server connection, or the input method's connection if there is one, to
+    /// become readable. Pending requests are flushed first, since they may generate the events
+    /// being waited for. Waiting also stops once a drop target that hasn't answered is due to be
+    /// given up on.
+    fn wait(&self, timeout: Option<Duration>) {
+        self.connection.flush();
+        let timeout = match (timeout, self.poll_timeout()) {
+            (Some(timeout), Some(drag_timeout)) => Some(timeout.min(drag_timeout)),
+            (timeout, drag_timeout) => timeout.or(drag_timeout),
+        };
+        let fd = self.connection.as_raw_fd();
+        #[cfg(feature = "ime")]
+        if let Some(ime_fd) = self.ime.raw_fd() {
+            return wait_until_any_readable(&[fd, ime_fd], timeout);
+        }
+        wait_until_readable(fd, timeout);
+    }
+
+    /// Waking sends a `ClientMessage` to the window, which has no `WindowEvent` equivalent and is
+    /// skipped by `poll_event`.
+    fn wake_fn(&self) -> Option<WakeFn> {
+        let sender = ClientMessageSender::new(self.window_id, self.atoms.wake);
+        Some(Arc::new(move || sender.send()))
+    }
+
+    /// A drag's target is given up on by `poll_event` once it has failed to answer in time.
+    fn poll_timeout(&self) -> Option<Duration> {
+        self.drag_source.timeout()
+    }
+
+    fn set_file_drop_enabled(&self, enabled: bool) {
+        self.drop_target.set_enabled(self, enabled);
+    }
+
+    fn set_drop_accepted(&self, accepted: bool) {
+        self.drop_target.set_accepted(self, accepted);
+    }
+
+    fn start_drag(&self, data: DragData) -> bool {
+        self.drag_source.start(self, data)
+    }
+
+    fn set_capture_on_press(&self, capture: bool) {
+        self.capture.set_enabled(self, capture);
+    }
+
+    fn set_xembed_enabled(&self, enabled: bool) {
+        self.xembed.set_enabled(enabled);
+    }
+}
+
+impl Drop for EventSourceImpl {
+    /// Stops advertising XDND support, since nothing would answer drops onto the window anymore.
+    fn drop(&mut self) {
+        self.drop_target.set_enabled(self, false);
+    }
+}
+
+impl EventSourceImpl {
+    /// Translates a raw XCB event into a `WindowEvent`, if it has an equivalent.
+    fn convert_event(&self, xcb_event: &xcb::GenericEvent) -> Option<WindowEvent> {
+        let r = xcb_event.response_type() & !0x80;
+        match r {
+            xcb::EXPOSE => {
+                let expose: &xcb::ExposeEvent = unsafe { xcb::cast_event(xcb_event) };
+                self.exposures.add(Rect {
+                    x: expose.x() as u32,
+                    y: expose.y() as u32,
+                    width: expose.width() as u32,
+                    height: expose.height() as u32,
+                });
+                None
+            }
+            xcb::MOTION_NOTIFY => {
+                let motion: &xcb::MotionNotifyEvent = unsafe { xcb::cast_event(xcb_event) };
+                self.drag_source.handle_motion(
+                    self,
+                    motion.root_x(),
+                    motion.root_y(),
+                    motion.time(),
+                );
+                Some(WindowEvent::CursorMovement(
+                    motion.event_x() as f32 / self.size_xy.0 as f32,
+                    motion.event_y() as f32 / self.size_xy.1 as f32,
+                ))
+            }
+            xcb::KEY_PRESS => {
+                let key: &xcb::KeyPressEvent = unsafe { xcb::cast_event(xcb_event) };
+                // Keys that the input method doesn't consume are sent back, and reported then.
+                #[cfg(feature = "ime")]
+                if self.ime.forward_key(&convert_key_event(key)) {
+                    return None;
+                }
+                Some(self.keys.press(key.detail()))
+            }
+            xcb::KEY_RELEASE => {
+                let key: &xcb::KeyReleaseEvent = unsafe { xcb::cast_event(xcb_event) };
+                // Releases follow their presses through the input method.
+                #[cfg(feature = "ime")]
+                if self.ime.forward_key(&convert_key_event(key)) {
+                    return None;
                 }
+                self.keys.release(key.detail())
+            }
+            xcb::FOCUS_OUT => {
+                self.keys.clear();
+                None
+            }
+            xcb::BUTTON_PRESS => {
+                let button: &xcb::ButtonPressEvent = unsafe { xcb::cast_event(xcb_event) };
+                let dismissed = self
+                    .popup
+                    .as_ref()
+                    .and_then(|popup| popup.handle_press(self, button.event_x(), button.event_y()));
+                if dismissed.is_some() {
+                    return dismissed;
+                }
+                self.xembed.handle_press(self, button.time());
+                let button_detail = button.detail();
+                let event = convert_mouse_button_detail(button_detail).map(WindowEvent::MouseClick);
+                // Popups capture the pointer themselves while they are open.
+                if event.is_some() && self.popup.is_none() {
+                    self.capture.handle_press(self, button_detail);
+                }
+                event
+            }
+            xcb::BUTTON_RELEASE => {
+                let button: &xcb::ButtonReleaseEvent = unsafe { xcb::cast_event(xcb_event) };
+                self.drag_source.handle_release(self, button.time());
+                self.capture.handle_release(self, button.detail());
+                convert_mouse_button_detail(button.detail()).map(WindowEvent::MouseRelease)
+            }
+            xcb::CLIENT_MESSAGE => {
+                let message: &xcb::ClientMessageEvent = unsafe { xcb::cast_event(xcb_event) };
+                if message.type_() == self.atoms.frame {
+                    let frame_timer = self.frame_timer.lock().unwrap();
+                    // Messages sent before the timer was stopped may still be queued.
+                    return frame_timer.as_ref().map(FrameTimer::frame_event);
+                }
+                if message.format() != 32 {
+                    return None;
+                }
+                let data = message.data().data32();
+                let data = [data[0], data[1], data[2], data[3], data[4]];
+                let event = self
+                    .drag_source
+                    .handle_client_message(self, message.type_(), data)
+                    .or_else(|| {
+                        self.drop_target
+                            .handle_client_message(self, message.type_(), data)
+                    })
+                    .or_else(|| self.xembed.handle_client_message(message.type_(), data));
+                // Forwarded key releases aren't sent to the window once it loses the focus.
+                if event == Some(WindowEvent::Focused(false)) {
+                    self.keys.clear();
+                }
+                event
+            }
+            xcb::SELECTION_NOTIFY => {
+                let notify: &xcb::SelectionNotifyEvent = unsafe { xcb::cast_event(xcb_event) };
+                if notify.selection() != self.atoms.xdnd_selection {
+                    return None;
+                }
+                self.drop_target
+                    .handle_selection_notify(self, notify.property())
+            }
+            xcb::SELECTION_REQUEST => {
+                let request: &xcb::SelectionRequestEvent = unsafe { xcb::cast_event(xcb_event) };
+                let request = SelectionRequest {
+                    requestor: request.requestor(),
+                    selection: request.selection(),
+                    target: request.target(),
+                    property: request.property(),
+                    time: request.time(),
+                };
+                answer_request(self, &self.atoms, &self.selections, request);
+                None
             }
+            _ => None,
         }
     }
 }
 
+/// The input method is spoken to over an `x11rb` connection, so key presses and releases, which
+/// share a layout, are converted to `x11rb`'s representation before being forwarded to it.
+#[cfg(feature = "ime")]
+fn convert_key_event(key: &xcb::KeyPressEvent) -> x11rb::protocol::xproto::KeyPressEvent {
+    x11rb::protocol::xproto::KeyPressEvent {
+        response_type: key.response_type(),
+        detail: key.detail(),
+        sequence: 0,
+        time: key.time(),
+        root: key.root(),
+        event: key.event(),
+        child: key.child(),
+        root_x: key.root_x(),
+        root_y: key.root_y(),
+        event_x: key.event_x(),
+        event_y: key.event_y(),
+        state: key.state().into(),
+        same_screen: key.same_screen(),
+    }
+}
+
 fn convert_mouse_button_detail(detail: u8) -> Option<crate::event::MouseButton> {
     use crate::event::MouseButton;
     match detail {
@@ -65,3 +327,223 @@ fn convert_mouse_button_detail(detail: u8) -> Option<crate::event::MouseButton>
         _ => None,
     }
 }
+
+impl X11Connection for EventSourceImpl {
+    fn send_client_message(&self, window: u32, type_: u32, data: [u32; 5]) {
+        let message = xcb::ClientMessageEvent::new(
+            32,
+            window,
+            type_,
+            xcb::ClientMessageData::from_data32(data),
+        );
+        // The other client's window may already have been destroyed.
+        drop(xcb::send_event_checked(
+            &self.connection,
+            false,
+            window,
+            xcb::EVENT_MASK_NO_EVENT,
+            &message,
+        ));
+    }
+
+    /// The requestor's window may already have been destroyed.
+    fn set_property8(&self, window: u32, property: u32, type_: u32, data: &[u8]) {
+        drop(xcb::change_property_checked(
+            &self.connection,
+            xcb::PROP_MODE_REPLACE as u8,
+            window,
+            property,
+            type_,
+            8,
+            data,
+        ));
+    }
+
+    fn set_property32(&self, window: u32, property: u32, type_: u32, values: &[u32]) {
+        drop(xcb::change_property_checked(
+            &self.connection,
+            xcb::PROP_MODE_REPLACE as u8,
+            window,
+            property,
+            type_,
+            32,
+            values,
+        ));
+    }
+
+    /// The window may already have been destroyed when the event source is dropped.
+    fn delete_property(&self, window: u32, property: u32) {
+        drop(xcb::delete_property_checked(
+            &self.connection,
+            window,
+            property,
+        ));
+    }
+
+    fn grab_pointer(&self, window: u32, owner_events: bool) -> bool {
+        let event_mask = xcb::EVENT_MASK_BUTTON_PRESS
+            | xcb::EVENT_MASK_BUTTON_RELEASE
+            | xcb::EVENT_MASK_POINTER_MOTION;
+        xcb::grab_pointer(
+            &self.connection,
+            owner_events,
+            window,
+            event_mask as u16,
+            xcb::GRAB_MODE_ASYNC as u8,
+            xcb::GRAB_MODE_ASYNC as u8,
+            xcb::NONE,
+            xcb::NONE,
+            xcb::CURRENT_TIME,
+        )
+        .get_reply()
+        .is_ok_and(|reply| reply.status() == xcb::GRAB_STATUS_SUCCESS as u8)
+    }
+
+    fn ungrab_pointer(&self) {
+        xcb::ungrab_pointer(&self.connection, xcb::CURRENT_TIME);
+    }
+
+    fn flush(&self) {
+        self.connection.flush();
+    }
+}
+
+impl XdndConnection for EventSourceImpl {
+    fn get_property(&self, window: u32, property: u32, delete: bool) -> Option<Vec<u8>> {
+        let reply = xcb::get_property(
+            &self.connection,
+            delete,
+            window,
+            property,
+            xcb::ATOM_ANY,
+            0,
+            u32::MAX / 4,
+        )
+        .get_reply()
+        .ok()?;
+        if reply.type_() == xcb::NONE {
+            return None;
+        }
+        Some(reply.value::<u8>().to_vec())
+    }
+
+    fn convert_selection(
+        &self,
+        requestor: u32,
+        selection: u32,
+        target: u32,
+        property: u32,
+        time: u32,
+    ) {
+        xcb::convert_selection(
+            &self.connection,
+            requestor,
+            selection,
+            target,
+            property,
+            time,
+        );
+    }
+
+    fn translate_from_root(&self, window: u32, x: i16, y: i16) -> Option<(i16, i16)> {
+        let reply = xcb::translate_coordinates(&self.connection, self.root_id, window, x, y)
+            .get_reply()
+            .ok()?;
+        Some((reply.dst_x(), reply.dst_y()))
+    }
+
+    fn child_at(&self, window: u32, x: i16, y: i16) -> Option<u32> {
+        let reply = xcb::translate_coordinates(&self.connection, self.root_id, window, x, y)
+            .get_reply()
+            .ok()?;
+        Some(reply.child()).filter(|&child| child != xcb::NONE)
+    }
+
+    fn intern_atom(&self, name: &str) -> Option<u32> {
+        let reply = xcb::intern_atom(&self.connection, false, name)
+            .get_reply()
+            .ok()?;
+        Some(reply.atom())
+    }
+}
+
+impl SelectionConnection for EventSourceImpl {
+    fn set_selection_owner(&self, owner: u32, selection: u32, time: u32) {
+        xcb::set_selection_owner(&self.connection, owner, selection, time);
+    }
+
+    fn send_selection_notify(
+        &self,
+        requestor: u32,
+        selection: u32,
+        target: u32,
+        property: u32,
+        time: u32,
+    ) {
+        let notify = xcb::SelectionNotifyEvent::new(time, requestor, selection, target, property);
+        drop(xcb::send_event_checked(
+            &self.connection,
+            false,
+            requestor,
+            xcb::EVENT_MASK_NO_EVENT,
+            &notify,
+        ));
+    }
+
+    fn maximum_request_bytes(&self) -> usize {
+        self.connection.get_maximum_request_length() as usize * 4
+    }
+}
+
+impl AsRawFd for EventSourceImpl {
+    fn as_raw_fd(&self) -> RawFd {
+        self.connection.as_raw_fd()
+    }
+}
+
+/// Sends empty `ClientMessage`s of a given type to a window from other threads. With an empty
+/// event mask, the X server delivers them to the client that created the window.
+///
+/// A separate connection is used, since `libxcb` may read incoming events from the socket while
+/// sending requests, and events read by another thread wouldn't make the shared connection's file
+/// descriptor readable. It is opened up front on the thread creating the sender, so that no
+/// connection handshake happens on the sending threads, such as the audio thread.
+struct ClientMessageSender {
+    window_id: u32,
+    type_: xcb::Atom,
+    /// `None` if the connection couldn't be opened, in which case nothing is sent.
+    connection: Option<xcb::Connection>,
+}
+
+impl ClientMessageSender {
+    fn new(window_id: u32, type_: xcb::Atom) -> Self {
+        Self {
+            window_id,
+            type_,
+            connection: xcb::Connection::connect(None).ok().map(|(c, _)| c),
+        }
+    }
+
+    fn send(&self) {
+        let connection = match &self.connection {
+            Some(connection) => connection,
+            None => return,
+        };
+        let message = xcb::ClientMessageEvent::new(
+            32,
+            self.window_id,
+            self.type_,
+            xcb::ClientMessageData::from_data32([0; 5]),
+        );
+        // The window may already have been destroyed, in which case the `BadWindow` error is
+        // discarded along with the cookie.
+        drop(xcb::send_event_checked(
+            connection,
+            false,
+            self.window_id,
+            xcb::EVENT_MASK_NO_EVENT,
+            &message,
+        ));
+        connection.flush();
+    }
+}
@@ -1,8 +1,19 @@
 //! Provides window setup logic specific to the Unix platform.
 
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle, XcbHandle};
 
-use crate::platform::EditorWindowBackend;
+use crate::platform::x11::atoms::Atoms;
+use crate::platform::x11::image::{clip_damage, decode_image, Exposures, ImageTarget, Presenter};
+#[cfg(feature = "ime")]
+use crate::platform::x11::ime::InputMethod;
+use crate::platform::x11::selection::{read_text, text_targets, SelectionReader, Selections};
+use crate::platform::x11::wait::wait_until_readable;
+use crate::platform::x11::xembed::XEMBED_INFO;
+use crate::platform::{EditorWindowBackend, Image, PresentMethod, Rect};
 
 /// "User-specified size" flag for WM_NORMAL_HINTS
 const USSIZE: u32 = 2;
@@ -12,40 +23,75 @@ const PMINSIZE: u32 = 16;
 const PMAXSIZE: u32 = 32;
 
 pub(in crate::platform) struct EditorWindowImpl {
-    /// This is always `Some` throughout the usable lifetime of the `EditorWindow`; it will only be
-    /// `None` during the `Drop` implementation.
-    pub connection: Option<xcb::base::Connection>,
-    window_id: u32,
+    /// Shared with the corresponding `EventSourceImpl`. The `xcb` crate disconnects from the X
+    /// server when the `Connection` is dropped, which happens once both have been dropped, in
+    /// either order.
+    pub connection: Arc<xcb::base::Connection>,
+    pub window_id: u32,
+    pub root_id: u32,
+    /// Whether this is a popup created with `create_popup`, rather than the editor's window.
+    pub popup: bool,
+    gc_id: u32,
+    depth: u8,
+    /// Whether the X server expects image data in big-endian byte order.
+    image_msb_first: bool,
+    size_xy: (i32, i32),
+    presenter: Mutex<Presenter>,
+    /// Filled in by the corresponding `EventSourceImpl` as `Expose` events arrive, and drained by
+    /// the next `present_pixels` call.
+    pub exposures: Exposures,
+    pub atoms: Atoms,
+    /// Data offered through the selections owned by the window, handed out by the corresponding
+    /// `EventSourceImpl` as other clients request it.
+    pub selections: Selections,
+    /// Opened the first time another client's selection is read.
+    selection_reader: Mutex<Option<SelectionReaderImpl>>,
+    /// Fed key presses by the corresponding `EventSourceImpl`.
+    #[cfg(feature = "ime")]
+    pub ime: InputMethod,
 }
 
 unsafe impl HasRawWindowHandle for EditorWindowImpl {
     fn raw_window_handle(&self) -> RawWindowHandle {
         let mut handle = XcbHandle::empty();
-        handle.connection =
-            self.connection.as_ref().unwrap().get_raw_conn() as *mut std::ffi::c_void;
+        handle.connection = self.connection.get_raw_conn() as *mut std::ffi::c_void;
         handle.window = self.window_id;
         RawWindowHandle::Xcb(handle)
     }
 }
 
 impl Drop for EditorWindowImpl {
-    /// The `xcb` crate will disconnect the connection to the backend when the `Connection` type is
-    /// dropped. Here, we convert the wrapper back into a raw connection, to prevent it from being
-    /// disconnected a second time in the `EventSource`.
+    /// The X server only cleans up a client's resources once it disconnects. Since the connection
+    /// may outlive the window (if the `EventSource` is still around), the window and its graphics
+    /// context are explicitly destroyed here.
+    ///
+    /// Hosts often destroy the parent window (and with it, this window) before closing the editor.
+    /// The checked request variants are used so that the resulting `BadWindow` error is discarded
+    /// along with the cookie, rather than being delivered to the `EventSource`.
     fn drop(&mut self) {
-        self.connection.take().unwrap().into_raw_conn();
+        let mut presenter =
+            std::mem::replace(self.presenter.get_mut().unwrap(), Presenter::Uninitialized);
+        presenter.release(self);
+        drop(xcb::destroy_window_checked(
+            &self.connection,
+            self.window_id,
+        ));
+        drop(xcb::free_gc_checked(&self.connection, self.gc_id));
+        self.connection.flush();
     }
 }
 
-impl EditorWindowBackend for EditorWindowImpl {
-    /// The VST API provides an XCB handle on Unix, so the window is setup using `xcb`.
-    ///
-    /// All XCB operations rely on a connection handle to the XCB backend. Conveniently, the XCB
-    /// `create_window` function takes a parent window id argument.
-    ///
-    /// XCB operations can be called from any thread - unlike the other platforms, there are
-    /// practically no restrictions on the control flow of the windowing logic.
-    fn build(parent: *mut std::os::raw::c_void, size_xy: (i32, i32)) -> Self {
+/// Where a window is created.
+enum Placement {
+    /// Inside the host's window, at its top-left.
+    Embedded { parent: u32 },
+    /// Above all other windows, at the given root window coordinates.
+    Popup { x: i16, y: i16 },
+}
+
+impl EditorWindowImpl {
+    /// Creates and maps a window, with its own connection to the X server.
+    fn create(placement: Placement, size_xy: (i32, i32)) -> Self {
         let (connection, screen_num) = xcb::base::Connection::connect(None).unwrap();
         let setup = connection.get_setup();
         let screen = setup.roots().nth(screen_num as usize).expect("Get screen");
@@ -59,13 +105,20 @@ impl EditorWindowBackend for EditorWindowImpl {
 
         let event_mask = xcb::EVENT_MASK_EXPOSURE
             | xcb::EVENT_MASK_KEY_PRESS
+            | xcb::EVENT_MASK_KEY_RELEASE
+            | xcb::EVENT_MASK_FOCUS_CHANGE
             | xcb::EVENT_MASK_BUTTON_PRESS
             | xcb::EVENT_MASK_BUTTON_RELEASE
             | xcb::EVENT_MASK_POINTER_MOTION;
         let wid = connection.generate_id();
-        let parent = parent as u32;
+        let (parent, x, y, window_type) = match placement {
+            Placement::Embedded { parent } => (parent, 0, 0, "_NET_WM_WINDOW_TYPE_DIALOG"),
+            Placement::Popup { x, y } => (screen.root(), x, y, "_NET_WM_WINDOW_TYPE_POPUP_MENU"),
+        };
+        let popup = matches!(placement, Placement::Popup { .. });
         let values = [
             (xcb::CW_BACK_PIXEL, screen.black_pixel()),
+            (xcb::CW_OVERRIDE_REDIRECT, u32::from(popup)),
             (xcb::CW_EVENT_MASK, event_mask),
         ];
 
@@ -74,8 +127,8 @@ impl EditorWindowBackend for EditorWindowImpl {
             xcb::COPY_FROM_PARENT as u8,
             wid,
             parent,
-            0,
-            0,
+            x,
+            y,
             size_xy.0 as u16,
             size_xy.1 as u16,
             0,
@@ -85,8 +138,7 @@ impl EditorWindowBackend for EditorWindowImpl {
         );
 
         let net_wm_window_type = xcb_intern_string(&connection, "_NET_WM_WINDOW_TYPE");
-        let net_wm_window_type_dialog =
-            xcb_intern_string(&connection, "_NET_WM_WINDOW_TYPE_DIALOG");
+        let net_wm_window_type_value = xcb_intern_string(&connection, window_type);
         xcb::change_property(
             &connection,
             xcb::PROP_MODE_REPLACE as u8,
@@ -94,7 +146,7 @@ impl EditorWindowBackend for EditorWindowImpl {
             net_wm_window_type,
             xcb::ATOM_ATOM,
             32,
-            &[net_wm_window_type_dialog],
+            &[net_wm_window_type_value],
         );
 
         let wm_normal_hints = xcb_intern_string(&connection, "WM_NORMAL_HINTS");
@@ -116,23 +168,468 @@ impl EditorWindowBackend for EditorWindowImpl {
             &size_hints,
         );
 
+        let atoms = Atoms::from_interned(&xcb_intern_atoms(&connection, Atoms::NAMES));
+        xcb_enable_detectable_auto_repeat(&connection);
+
+        if !popup {
+            xcb::change_property(
+                &connection,
+                xcb::PROP_MODE_REPLACE as u8,
+                wid,
+                atoms.xembed_info,
+                atoms.xembed_info,
+                32,
+                &XEMBED_INFO,
+            );
+        }
+
         xcb::xproto::map_window(&connection, wid);
         connection.flush();
 
         drop(_cookie);
 
+        let depth = screen.root_depth();
+        let image_msb_first = setup.image_byte_order() == xcb::IMAGE_ORDER_MSB_FIRST as u8;
+
+        let root_id = screen.root();
+
         Self {
-            connection: Some(connection),
+            connection: Arc::new(connection),
             window_id: wid,
+            root_id,
+            popup,
+            gc_id: foreground,
+            depth,
+            image_msb_first,
+            size_xy,
+            presenter: Mutex::new(Presenter::Uninitialized),
+            exposures: Exposures::default(),
+            atoms,
+            selections: Selections::default(),
+            selection_reader: Mutex::new(None),
+            // Popups never have the focus, so connecting them to the server would be wasted.
+            #[cfg(feature = "ime")]
+            ime: if popup {
+                InputMethod::disconnected()
+            } else {
+                InputMethod::connect(wid)
+            },
         }
     }
 }
 
+impl EditorWindowBackend for EditorWindowImpl {
+    /// The VST API provides an XCB handle on Unix, so the window is setup using `xcb`.
+    ///
+    /// All XCB operations rely on a connection handle to the XCB backend. Conveniently, the XCB
+    /// `create_window` function takes a parent window id argument.
+    ///
+    /// XCB operations can be called from any thread - unlike the other platforms, there are
+    /// practically no restrictions on the control flow of the windowing logic.
+    fn build(parent: *mut std::os::raw::c_void, size_xy: (i32, i32)) -> Self {
+        Self::create(
+            Placement::Embedded {
+                parent: parent as u32,
+            },
+            size_xy,
+        )
+    }
+
+    /// Pixels are uploaded using MIT-SHM if possible, or `put_image` otherwise, drawn with the
+    /// graphics context created in `build`. Areas exposed since the last frame are redrawn along
+    /// with the dirty ones.
+    fn present_pixels(&self, pixels: &[u32], width: u32, height: u32, dirty: &[Rect]) {
+        let mut damage = self.exposures.take();
+        damage.extend_from_slice(dirty);
+        let damage = clip_damage(&damage, width, height);
+        self.presenter
+            .lock()
+            .unwrap()
+            .present(self, pixels, width, height, &damage);
+    }
+
+    fn present_method(&self) -> PresentMethod {
+        let size_xy = (self.size_xy.0 as u32, self.size_xy.1 as u32);
+        self.presenter.lock().unwrap().method(self, size_xy)
+    }
+
+    /// The window's contents are read back with `get_image`, which fails if the window isn't
+    /// viewable.
+    fn capture(&self) -> Option<Image> {
+        let (width, height) = (self.size_xy.0 as u32, self.size_xy.1 as u32);
+        let reply = xcb::get_image(
+            &self.connection,
+            xcb::IMAGE_FORMAT_Z_PIXMAP as u8,
+            self.window_id,
+            0,
+            0,
+            width as u16,
+            height as u16,
+            !0,
+        )
+        .get_reply()
+        .ok()?;
+        decode_image(reply.data(), width, height, self.image_msb_first)
+    }
+
+    /// If the window owns the clipboard, its own text is returned directly, since the event source
+    /// can't answer requests while this thread waits. Otherwise, the owner is asked for the text
+    /// over a separate connection, so that no events meant for the event source are consumed.
+    fn clipboard_text(&self) -> Option<String> {
+        let owner = xcb::get_selection_owner(&self.connection, self.atoms.clipboard)
+            .get_reply()
+            .ok()?
+            .owner();
+        if owner == self.window_id {
+            let text = self
+                .selections
+                .get(self.atoms.clipboard, self.atoms.utf8_string)?;
+            return String::from_utf8(text).ok();
+        }
+        if owner == xcb::NONE {
+            return None;
+        }
+        let mut reader = self.selection_reader.lock().unwrap();
+        if reader.is_none() {
+            *reader = SelectionReaderImpl::connect();
+        }
+        read_text(reader.as_ref()?, &self.atoms, self.atoms.clipboard)
+    }
+
+    /// The window takes ownership of the clipboard, and the event source answers other clients'
+    /// requests for its contents.
+    fn set_clipboard_text(&self, text: &str) {
+        self.selections
+            .offer(self.atoms.clipboard, text_targets(&self.atoms, text));
+        xcb::set_selection_owner(
+            &self.connection,
+            self.window_id,
+            self.atoms.clipboard,
+            xcb::CURRENT_TIME,
+        );
+        self.connection.flush();
+    }
+
+    /// The popup's position is translated from the window's coordinates to the root window's.
+    fn create_popup(&self, position: (i32, i32), size_xy: (i32, i32)) -> Option<Self> {
+        let position = xcb::translate_coordinates(
+            &self.connection,
+            self.window_id,
+            self.root_id,
+            clamp_coordinate(position.0),
+            clamp_coordinate(position.1),
+        )
+        .get_reply()
+        .ok()?;
+        let (x, y) = (position.dst_x(), position.dst_y());
+        Some(Self::create(Placement::Popup { x, y }, size_xy))
+    }
+
+    fn cursor_position(&self) -> Option<(i32, i32)> {
+        let reply = xcb::query_pointer(&self.connection, self.window_id)
+            .get_reply()
+            .ok()?;
+        if !reply.same_screen() {
+            return None;
+        }
+        Some((reply.win_x().into(), reply.win_y().into()))
+    }
+
+    fn position(&self) -> Option<(i32, i32)> {
+        let reply = xcb::get_geometry(&self.connection, self.window_id)
+            .get_reply()
+            .ok()?;
+        Some((reply.x().into(), reply.y().into()))
+    }
+
+    /// Coordinates are signed 16 bit values in the X11 protocol, which are sign-extended to 32
+    /// bits in value lists.
+    fn set_position(&self, x: i32, y: i32) {
+        let (x, y) = (clamp_coordinate(x), clamp_coordinate(y));
+        xcb::configure_window(
+            &self.connection,
+            self.window_id,
+            &[
+                (xcb::CONFIG_WINDOW_X as u16, i32::from(x) as u32),
+                (xcb::CONFIG_WINDOW_Y as u16, i32::from(y) as u32),
+            ],
+        );
+        self.connection.flush();
+    }
+
+    fn screen_position(&self) -> Option<(i32, i32)> {
+        let reply =
+            xcb::translate_coordinates(&self.connection, self.window_id, self.root_id, 0, 0)
+                .get_reply()
+                .ok()?;
+        Some((reply.dst_x().into(), reply.dst_y().into()))
+    }
+
+    /// The root window's geometry is queried, since the screen may have been resized since the
+    /// connection was opened.
+    fn screen_size(&self) -> Option<(i32, i32)> {
+        let reply = xcb::get_geometry(&self.connection, self.root_id)
+            .get_reply()
+            .ok()?;
+        Some((reply.width().into(), reply.height().into()))
+    }
+
+    fn x11_window_id(&self) -> Option<u32> {
+        Some(self.window_id)
+    }
+
+    /// The pointer is grabbed with owner events, on the connection shared with the event source.
+    fn grab_pointer(&self) -> bool {
+        let event_mask = xcb::EVENT_MASK_BUTTON_PRESS
+            | xcb::EVENT_MASK_BUTTON_RELEASE
+            | xcb::EVENT_MASK_POINTER_MOTION;
+        xcb::grab_pointer(
+            &self.connection,
+            true,
+            self.window_id,
+            event_mask as u16,
+            xcb::GRAB_MODE_ASYNC as u8,
+            xcb::GRAB_MODE_ASYNC as u8,
+            xcb::NONE,
+            xcb::NONE,
+            xcb::CURRENT_TIME,
+        )
+        .get_reply()
+        .is_ok_and(|reply| reply.status() == xcb::GRAB_STATUS_SUCCESS as u8)
+    }
+
+    fn ungrab_pointer(&self) {
+        xcb::ungrab_pointer(&self.connection, xcb::CURRENT_TIME);
+        self.connection.flush();
+    }
+
+    #[cfg(feature = "ime")]
+    fn set_ime_position(&self, x: i32, y: i32) {
+        self.ime.set_spot(clamp_coordinate(x), clamp_coordinate(y));
+    }
+}
+
+impl ImageTarget for EditorWindowImpl {
+    fn maximum_request_bytes(&self) -> usize {
+        self.connection.get_maximum_request_length() as usize * 4
+    }
+
+    fn image_msb_first(&self) -> bool {
+        self.image_msb_first
+    }
+
+    fn put_image(&self, rect: &Rect, data: &[u8]) {
+        xcb::put_image(
+            &self.connection,
+            xcb::IMAGE_FORMAT_Z_PIXMAP as u8,
+            self.window_id,
+            self.gc_id,
+            rect.width as u16,
+            rect.height as u16,
+            rect.x as i16,
+            rect.y as i16,
+            0,
+            self.depth,
+            data,
+        );
+    }
+
+    fn shm_attach(&self, shmid: u32) -> Option<u32> {
+        // Requests for missing extensions would shut down the connection.
+        let present = self
+            .connection
+            .get_extension_data(xcb::shm::id())
+            .is_some_and(|data| data.present());
+        if !present {
+            return None;
+        }
+        xcb::shm::query_version(&self.connection).get_reply().ok()?;
+        let shmseg = self.connection.generate_id();
+        xcb::shm::attach_checked(&self.connection, shmseg, shmid, false)
+            .request_check()
+            .ok()?;
+        Some(shmseg)
+    }
+
+    fn shm_detach(&self, shmseg: u32) {
+        xcb::shm::detach(&self.connection, shmseg);
+    }
+
+    fn shm_put_image(&self, shmseg: u32, total_width: u32, total_height: u32, rect: &Rect) {
+        xcb::shm::put_image(
+            &self.connection,
+            self.window_id,
+            self.gc_id,
+            total_width as u16,
+            total_height as u16,
+            rect.x as u16,
+            rect.y as u16,
+            rect.width as u16,
+            rect.height as u16,
+            rect.x as i16,
+            rect.y as i16,
+            self.depth,
+            xcb::IMAGE_FORMAT_Z_PIXMAP as u8,
+            0,
+            shmseg,
+            0,
+        );
+    }
+
+    fn sync(&self) {
+        let _ = xcb::get_input_focus(&self.connection).get_reply();
+    }
+
+    fn flush(&self) {
+        self.connection.flush();
+    }
+}
+
+/// Clamps a coordinate to the range of X11 coordinates.
+fn clamp_coordinate(value: i32) -> i16 {
+    value.clamp(i16::MIN.into(), i16::MAX.into()) as i16
+}
+
 /// With XCB, some window properties are identified using `Atom`s, which are identifiers for
 /// strings that have been previously interned.
 fn xcb_intern_string(connection: &xcb::Connection, value: &str) -> xcb::Atom {
-    match xcb::intern_atom(&connection, false, value).get_reply() {
+    match xcb::intern_atom(connection, false, value).get_reply() {
         Ok(reply) => reply.atom(),
         Err(_) => panic!("could not intern {} atom", value),
     }
 }
+
+/// Interns several strings at once, waiting for a single round trip to the X server.
+fn xcb_intern_atoms(connection: &xcb::Connection, values: &[&str]) -> Vec<xcb::Atom> {
+    let cookies: Vec<_> = values
+        .iter()
+        .map(|value| xcb::intern_atom(connection, false, value))
+        .collect();
+    cookies
+        .into_iter()
+        .zip(values)
+        .map(|(cookie, value)| match cookie.get_reply() {
+            Ok(reply) => reply.atom(),
+            Err(_) => panic!("could not intern {} atom", value),
+        })
+        .collect()
+}
+
+/// Asks the X server to report held keys as repeated presses followed by a single release, rather
+/// than as pairs of releases and presses. Servers without the XKB extension keep sending pairs.
+fn xcb_enable_detectable_auto_repeat(connection: &xcb::Connection) {
+    // Requests for missing extensions would shut down the connection.
+    let present = connection
+        .get_extension_data(xcb::xkb::id())
+        .is_some_and(|data| data.present());
+    let supported = present
+        && xcb::xkb::use_extension(connection, 1, 0)
+            .get_reply()
+            .is_ok_and(|reply| reply.supported());
+    if !supported {
+        return;
+    }
+    let flag = xcb::xkb::PER_CLIENT_FLAG_DETECTABLE_AUTO_REPEAT;
+    let _ = xcb::xkb::per_client_flags(
+        connection,
+        xcb::xkb::ID_USE_CORE_KBD as xcb::xkb::DeviceSpec,
+        flag,
+        flag,
+        0,
+        0,
+        0,
+    )
+    .get_reply();
+}
+
+/// Reads selections owned by other clients, using its own connection and an invisible window to
+/// receive the converted data.
+struct SelectionReaderImpl {
+    connection: xcb::Connection,
+    window_id: u32,
+}
+
+impl SelectionReaderImpl {
+    fn connect() -> Option<Self> {
+        let (connection, screen_num) = xcb::Connection::connect(None).ok()?;
+        let root = connection
+            .get_setup()
+            .roots()
+            .nth(screen_num as usize)?
+            .root();
+        let window_id = connection.generate_id();
+        xcb::create_window(
+            &connection,
+            xcb::COPY_FROM_PARENT as u8,
+            window_id,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            xcb::WINDOW_CLASS_INPUT_ONLY as u16,
+            xcb::COPY_FROM_PARENT,
+            &[],
+        );
+        Some(Self {
+            connection,
+            window_id,
+        })
+    }
+}
+
+impl SelectionReader for SelectionReaderImpl {
+    fn convert_selection(
+        &self,
+        selection: u32,
+        target: u32,
+        property: u32,
+        deadline: Instant,
+    ) -> bool {
+        xcb::convert_selection(
+            &self.connection,
+            self.window_id,
+            selection,
+            target,
+            property,
+            xcb::CURRENT_TIME,
+        );
+        self.connection.flush();
+        loop {
+            while let Some(event) = self.connection.poll_for_event() {
+                if event.response_type() & !0x80 != xcb::SELECTION_NOTIFY {
+                    continue;
+                }
+                let notify: &xcb::SelectionNotifyEvent = unsafe { xcb::cast_event(&event) };
+                // Answers to earlier conversions that timed out may still arrive.
+                if notify.selection() == selection && notify.target() == target {
+                    return notify.property() != xcb::NONE;
+                }
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            wait_until_readable(self.connection.as_raw_fd(), Some(remaining));
+        }
+    }
+
+    fn take_property(&self, property: u32) -> Option<(u32, Vec<u8>)> {
+        let reply = xcb::get_property(
+            &self.connection,
+            true,
+            self.window_id,
+            property,
+            xcb::ATOM_ANY,
+            0,
+            u32::MAX / 4,
+        )
+        .get_reply()
+        .ok()?;
+        if reply.type_() == xcb::NONE {
+            return None;
+        }
+        Some((reply.type_(), reply.value::<u8>().to_vec()))
+    }
+}
@@ -0,0 +1,51 @@
+//! The X11 atoms used by the window and event source, interned together when the window is
+//! created.
+
+/// Generates the `Atoms` struct, with one field per atom, along with the list of names to intern.
+macro_rules! atoms {
+    ($($field:ident = $name:expr,)*) => {
+        /// Atoms interned on the window's connection.
+        #[derive(Clone, Copy, Debug)]
+        pub struct Atoms {
+            $(pub $field: u32,)*
+        }
+
+        impl Atoms {
+            /// Names of every atom, in the order expected by `from_interned`.
+            pub const NAMES: &'static [&'static str] = &[$($name,)*];
+
+            /// Builds the `Atoms` from interned values, given in the same order as `NAMES`.
+            pub fn from_interned(values: &[u32]) -> Self {
+                let mut values = values.iter().copied();
+                Self {
+                    $($field: values.next().expect("missing interned atom"),)*
+                }
+            }
+        }
+    };
+}
+
+atoms! {
+    frame = "_VST_WINDOW_FRAME",
+    wake = "_VST_WINDOW_WAKE",
+    xdnd_aware = "XdndAware",
+    xdnd_enter = "XdndEnter",
+    xdnd_position = "XdndPosition",
+    xdnd_status = "XdndStatus",
+    xdnd_leave = "XdndLeave",
+    xdnd_drop = "XdndDrop",
+    xdnd_finished = "XdndFinished",
+    xdnd_selection = "XdndSelection",
+    xdnd_type_list = "XdndTypeList",
+    xdnd_action_copy = "XdndActionCopy",
+    text_uri_list = "text/uri-list",
+    drop_property = "_VST_WINDOW_DROP",
+    clipboard = "CLIPBOARD",
+    targets = "TARGETS",
+    incr = "INCR",
+    utf8_string = "UTF8_STRING",
+    text_plain_utf8 = "text/plain;charset=utf-8",
+    selection_property = "_VST_WINDOW_SELECTION",
+    xembed = "_XEMBED",
+    xembed_info = "_XEMBED_INFO",
+}
@@ -0,0 +1,77 @@
+//! Capturing of the pointer while mouse buttons are held, like `SetCapture` on Windows, so that
+//! drags which leave the window keep reporting motion and the release.
+//!
+//! X servers grab the pointer implicitly when a button is pressed, but that grab ends as soon as
+//! another client grabs the pointer, which some window managers and hosts do. While capturing, the
+//! event source makes an active grab instead, with owner events so that events over the window are
+//! reported as usual.
+
+use std::sync::Mutex;
+
+use super::connection::X11Connection;
+
+#[derive(Default)]
+struct CaptureState {
+    enabled: bool,
+    /// The buttons held down within the window, as a bit mask indexed by button number.
+    held: u32,
+    grabbed: bool,
+}
+
+/// The capture-on-press mode of an event source.
+pub struct PointerCapture {
+    window_id: u32,
+    state: Mutex<CaptureState>,
+}
+
+impl PointerCapture {
+    pub fn new(window_id: u32) -> Self {
+        Self {
+            window_id,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Starts or stops capturing the pointer on presses. Disabling releases the current capture,
+    /// if there is one.
+    pub fn set_enabled(&self, connection: &impl X11Connection, enabled: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.enabled = enabled;
+        if !enabled {
+            state.held = 0;
+            Self::release(&mut state, connection);
+        }
+    }
+
+    /// Captures the pointer when `button` is pressed, unless it is already captured.
+    pub fn handle_press(&self, connection: &impl X11Connection, button: u8) {
+        let mut state = self.state.lock().unwrap();
+        if !state.enabled {
+            return;
+        }
+        state.held |= button_mask(button);
+        if !state.grabbed {
+            state.grabbed = connection.grab_pointer(self.window_id, true);
+        }
+    }
+
+    /// Releases the capture once the last held button is released.
+    pub fn handle_release(&self, connection: &impl X11Connection, button: u8) {
+        let mut state = self.state.lock().unwrap();
+        state.held &= !button_mask(button);
+        if state.held == 0 {
+            Self::release(&mut state, connection);
+        }
+    }
+
+    fn release(state: &mut CaptureState, connection: &impl X11Connection) {
+        if std::mem::take(&mut state.grabbed) {
+            connection.ungrab_pointer();
+            connection.flush();
+        }
+    }
+}
+
+fn button_mask(button: u8) -> u32 {
+    1u32.checked_shl(button.into()).unwrap_or(0)
+}
@@ -0,0 +1,25 @@
+//! Requests to the X server shared by the protocols implemented in this module, such as pointer
+//! grabs and window properties.
+
+/// Basic operations on the X server, implemented by each X11 client library backend.
+pub trait X11Connection {
+    /// Sends a 32 bit `ClientMessage` to `window`, which is delivered to the client that created
+    /// it.
+    fn send_client_message(&self, window: u32, type_: u32, data: [u32; 5]);
+    /// Replaces a window property with a list of 8 bit values of the given type.
+    fn set_property8(&self, window: u32, property: u32, type_: u32, data: &[u8]);
+    /// Replaces a window property with a list of 32 bit values of the given type.
+    fn set_property32(&self, window: u32, property: u32, type_: u32, values: &[u32]);
+    /// Removes a window property.
+    fn delete_property(&self, window: u32, property: u32);
+    /// Actively grabs the pointer for `window`, so that it receives all motion and button events
+    /// until the grab is released. Returns whether the grab succeeded.
+    ///
+    /// With `owner_events`, events over the client's own windows are still reported to those
+    /// windows as usual, and only the remaining ones to `window`.
+    fn grab_pointer(&self, window: u32, owner_events: bool) -> bool;
+    /// Releases a pointer grab made with `grab_pointer`.
+    fn ungrab_pointer(&self);
+    /// Sends all buffered requests to the X server.
+    fn flush(&self);
+}
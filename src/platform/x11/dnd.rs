@@ -0,0 +1,628 @@
+//! Both sides of the XDND protocol, for dropping files onto the editor and dragging data out of
+//! it.
+//!
+//! See <https://freedesktop.org/wiki/Specifications/XDND/> for the protocol itself.
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::atoms::Atoms;
+use super::selection::{SelectionConnection, Selections, ATOM_ATOM};
+use crate::event::WindowEvent;
+use crate::platform::DragData;
+
+/// The version of the XDND protocol implemented here.
+const XDND_VERSION: u32 = 5;
+
+/// The oldest version of the XDND protocol that drop targets may implement to be offered drops.
+const MIN_TARGET_VERSION: u32 = 3;
+
+/// How long to wait for a drop target to answer once the button has been released, either with the
+/// `XdndStatus` deciding whether to drop, or with the `XdndFinished` ending the drop, before giving
+/// up on it.
+const DROP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Operations on the X server required for the XDND protocol, implemented by each X11 client
+/// library backend.
+pub trait XdndConnection: SelectionConnection {
+    /// Reads the whole value of a window property, returning `None` if it doesn't exist.
+    fn get_property(&self, window: u32, property: u32, delete: bool) -> Option<Vec<u8>>;
+    /// Asks the owner of `selection` to store its contents as `target` in `property` of
+    /// `requestor`.
+    fn convert_selection(
+        &self,
+        requestor: u32,
+        selection: u32,
+        target: u32,
+        property: u32,
+        time: u32,
+    );
+    /// Translates coordinates relative to the root window into coordinates relative to `window`.
+    fn translate_from_root(&self, window: u32, x: i16, y: i16) -> Option<(i16, i16)>;
+    /// Returns the mapped child of `window` containing the given root window coordinates, if any.
+    fn child_at(&self, window: u32, x: i16, y: i16) -> Option<u32>;
+    /// Returns the atom for `name`, interning it if necessary.
+    fn intern_atom(&self, name: &str) -> Option<u32>;
+}
+
+/// The state of a drag operation over the window.
+#[derive(Default)]
+struct DropState {
+    enabled: bool,
+    /// Whether the editor wants to accept the drop at the current position.
+    accepted: bool,
+    /// The window of the client that started the drag, if one is in progress.
+    source: Option<u32>,
+    /// Whether the dragged data is available as a list of files.
+    has_files: bool,
+    /// Whether an `XdndPosition` message still has to be answered with an `XdndStatus`.
+    awaiting_status: bool,
+    /// Whether the dropped data has been requested from the source, and hasn't arrived yet.
+    dropping: bool,
+}
+
+/// Handles XDND messages sent to the editor window, converting them into `WindowEvent`s.
+pub struct DropTarget {
+    atoms: Atoms,
+    window_id: u32,
+    size_xy: (i32, i32),
+    state: Mutex<DropState>,
+}
+
+impl DropTarget {
+    pub fn new(atoms: Atoms, window_id: u32, size_xy: (i32, i32)) -> Self {
+        Self {
+            atoms,
+            window_id,
+            size_xy,
+            state: Mutex::new(DropState {
+                accepted: true,
+                ..DropState::default()
+            }),
+        }
+    }
+
+    /// Advertises support for XDND on the window, or stops doing so.
+    pub fn set_enabled(&self, connection: &impl XdndConnection, enabled: bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.enabled == enabled {
+            return;
+        }
+        state.enabled = enabled;
+        if enabled {
+            connection.set_property32(
+                self.window_id,
+                self.atoms.xdnd_aware,
+                ATOM_ATOM,
+                &[XDND_VERSION],
+            );
+        } else {
+            connection.delete_property(self.window_id, self.atoms.xdnd_aware);
+        }
+        connection.flush();
+    }
+
+    /// Records whether the editor wants to accept the drop at the most recently hovered position,
+    /// answering the source right away if it's waiting.
+    pub fn set_accepted(&self, connection: &impl XdndConnection, accepted: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.accepted = accepted;
+        self.send_pending_status(connection, &mut state);
+    }
+
+    /// Answers the latest `XdndPosition` with the editor's last decision, if it hasn't been
+    /// answered yet. Called before polling for more events, so that a source is never left
+    /// waiting by an editor that doesn't call `set_accepted`.
+    pub fn flush_status(&self, connection: &impl XdndConnection) {
+        let mut state = self.state.lock().unwrap();
+        self.send_pending_status(connection, &mut state);
+    }
+
+    fn send_pending_status(&self, connection: &impl XdndConnection, state: &mut DropState) {
+        let source = match (state.awaiting_status, state.source) {
+            (true, Some(source)) => source,
+            _ => return,
+        };
+        state.awaiting_status = false;
+        let accept = state.accepted && state.has_files;
+        let action = if accept {
+            self.atoms.xdnd_action_copy
+        } else {
+            0
+        };
+        // An empty rectangle asks the source to send a new position whenever the pointer moves.
+        connection.send_client_message(
+            source,
+            self.atoms.xdnd_status,
+            [self.window_id, accept as u32, 0, 0, action],
+        );
+        connection.flush();
+    }
+
+    /// Handles a `ClientMessage` sent to the window, returning the corresponding `WindowEvent`
+    /// if there is one. Messages that aren't part of the XDND protocol are ignored.
+    pub fn handle_client_message(
+        &self,
+        connection: &impl XdndConnection,
+        type_: u32,
+        data: [u32; 5],
+    ) -> Option<WindowEvent> {
+        let mut state = self.state.lock().unwrap();
+        if !state.enabled {
+            return None;
+        }
+        let atoms = &self.atoms;
+        let source = data[0];
+        if type_ == atoms.xdnd_enter {
+            let types = if data[1] & 1 != 0 {
+                connection
+                    .get_property(source, atoms.xdnd_type_list, false)
+                    .map(|bytes| {
+                        bytes
+                            .chunks_exact(4)
+                            .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            } else {
+                data[2..5].to_vec()
+            };
+            state.source = Some(source);
+            state.has_files = types.contains(&atoms.text_uri_list);
+            state.awaiting_status = false;
+            state.dropping = false;
+            return None;
+        }
+        if state.source != Some(source) {
+            return None;
+        }
+        if type_ == atoms.xdnd_position {
+            state.awaiting_status = true;
+            if !state.has_files {
+                self.send_pending_status(connection, &mut state);
+                return None;
+            }
+            let (x, y) = ((data[2] >> 16) as i16, (data[2] & 0xffff) as i16);
+            let (x, y) = connection.translate_from_root(self.window_id, x, y)?;
+            Some(WindowEvent::FileHovered(
+                x as f32 / self.size_xy.0 as f32,
+                y as f32 / self.size_xy.1 as f32,
+            ))
+        } else if type_ == atoms.xdnd_leave {
+            state.source = None;
+            state.awaiting_status = false;
+            state.has_files.then_some(WindowEvent::FileHoverCancelled)
+        } else if type_ == atoms.xdnd_drop {
+            state.awaiting_status = false;
+            if state.accepted && state.has_files {
+                connection.convert_selection(
+                    self.window_id,
+                    atoms.xdnd_selection,
+                    atoms.text_uri_list,
+                    atoms.drop_property,
+                    data[2],
+                );
+                connection.flush();
+                state.dropping = true;
+                None
+            } else {
+                self.finish(connection, &mut state, false);
+                state.has_files.then_some(WindowEvent::FileHoverCancelled)
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Handles a `SelectionNotify` event, which is sent once the dropped data has been stored in
+    /// `property` of the window, or with no property if it couldn't be converted.
+    pub fn handle_selection_notify(
+        &self,
+        connection: &impl XdndConnection,
+        property: u32,
+    ) -> Option<WindowEvent> {
+        let mut state = self.state.lock().unwrap();
+        if !state.dropping || (property != 0 && property != self.atoms.drop_property) {
+            return None;
+        }
+        state.dropping = false;
+        let paths = if property == 0 {
+            vec![]
+        } else {
+            connection
+                .get_property(self.window_id, property, true)
+                .map(|bytes| parse_uri_list(&bytes))
+                .unwrap_or_default()
+        };
+        self.finish(connection, &mut state, !paths.is_empty());
+        if paths.is_empty() {
+            Some(WindowEvent::FileHoverCancelled)
+        } else {
+            Some(WindowEvent::FileDropped(paths))
+        }
+    }
+
+    /// Tells the source that the drop has been handled, ending the drag operation.
+    fn finish(&self, connection: &impl XdndConnection, state: &mut DropState, accepted: bool) {
+        if let Some(source) = state.source.take() {
+            let action = if accepted {
+                self.atoms.xdnd_action_copy
+            } else {
+                0
+            };
+            connection.send_client_message(
+                source,
+                self.atoms.xdnd_finished,
+                [self.window_id, accepted as u32, action, 0, 0],
+            );
+            connection.flush();
+        }
+    }
+}
+
+/// The phase of a drag operation started from the window.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DragPhase {
+    Idle,
+    /// The pointer is grabbed, and the drag follows it until the button is released.
+    Dragging,
+    /// The data was dropped onto a target, which hasn't finished handling it yet.
+    Dropping,
+}
+
+/// A window that the data is currently being dragged over.
+struct DragTarget {
+    window: u32,
+    /// The XDND protocol version used with the target.
+    version: u32,
+    /// Whether the target accepted a drop at the last position it answered for.
+    accepted: bool,
+    /// Whether an `XdndPosition` message hasn't been answered with an `XdndStatus` yet. Only one
+    /// position may be outstanding at a time.
+    awaiting_status: bool,
+    /// The latest position and timestamp, to be sent once the outstanding one has been answered.
+    pending_position: Option<(u32, u32)>,
+}
+
+/// The state of a drag operation started from the window.
+struct DragState {
+    phase: DragPhase,
+    /// The type the data is offered as.
+    type_: u32,
+    target: Option<DragTarget>,
+    /// Whether the button was released while waiting for the target's answer, so that the drop
+    /// has to be decided once it arrives.
+    released: bool,
+    /// The timestamp of the latest pointer event.
+    time: u32,
+    /// The outcome of a drag that ended without a drop, to be reported by the next poll.
+    ended: Option<bool>,
+    /// When to give up on a target that hasn't answered since the button was released.
+    deadline: Option<Instant>,
+}
+
+/// Drags data out of the editor window, converting the XDND messages sent back by drop targets
+/// into `WindowEvent`s.
+///
+/// The data itself is offered through the `XdndSelection` selection, which targets request once
+/// it has been dropped. These requests are answered by the event source like any other selection
+/// request.
+pub struct DragSource {
+    atoms: Atoms,
+    window_id: u32,
+    root_id: u32,
+    selections: Selections,
+    state: Mutex<DragState>,
+}
+
+impl DragSource {
+    pub fn new(atoms: Atoms, window_id: u32, root_id: u32, selections: Selections) -> Self {
+        Self {
+            atoms,
+            window_id,
+            root_id,
+            selections,
+            state: Mutex::new(DragState {
+                phase: DragPhase::Idle,
+                type_: 0,
+                target: None,
+                released: false,
+                time: 0,
+                ended: None,
+                deadline: None,
+            }),
+        }
+    }
+
+    /// Starts dragging `data`, following the pointer until the button is released. Returns
+    /// `false` if a drag is already in progress, including while the previous drop is still being
+    /// handled by its target, or if the pointer couldn't be grabbed.
+    pub fn start(&self, connection: &impl XdndConnection, data: DragData) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.phase != DragPhase::Idle {
+            return false;
+        }
+        let (type_, bytes) = match data {
+            DragData::Files(paths) => match absolute_paths(paths) {
+                Some(paths) => (self.atoms.text_uri_list, encode_uri_list(&paths)),
+                None => return false,
+            },
+            DragData::Mime { mime_type, data } => match connection.intern_atom(&mime_type) {
+                Some(type_) => (type_, data),
+                None => return false,
+            },
+        };
+        if !connection.grab_pointer(self.window_id, false) {
+            return false;
+        }
+        self.selections
+            .offer(self.atoms.xdnd_selection, vec![(type_, bytes)]);
+        connection.set_selection_owner(self.window_id, self.atoms.xdnd_selection, 0);
+        connection.flush();
+        *state = DragState {
+            phase: DragPhase::Dragging,
+            type_,
+            target: None,
+            released: false,
+            time: state.time,
+            ended: None,
+            deadline: None,
+        };
+        true
+    }
+
+    /// Follows the pointer to the given root window coordinates, telling the window under it
+    /// about the drag if it supports XDND.
+    pub fn handle_motion(&self, connection: &impl XdndConnection, x: i16, y: i16, time: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.time = time;
+        if state.phase != DragPhase::Dragging {
+            return;
+        }
+        let found = self.find_target(connection, x, y);
+        if state.target.as_ref().map(|target| target.window) != found.map(|(window, _)| window) {
+            if let Some(target) = state.target.take() {
+                self.send(connection, target.window, self.atoms.xdnd_leave, [0; 4]);
+            }
+            if let Some((window, version)) = found {
+                let data = [version << 24, state.type_, 0, 0];
+                self.send(connection, window, self.atoms.xdnd_enter, data);
+                state.target = Some(DragTarget {
+                    window,
+                    version,
+                    accepted: false,
+                    awaiting_status: false,
+                    pending_position: None,
+                });
+            }
+        }
+        let position = ((x as u16 as u32) << 16) | y as u16 as u32;
+        if let Some(target) = &mut state.target {
+            if target.awaiting_status {
+                target.pending_position = Some((position, time));
+            } else {
+                self.send_position(connection, target, position, time);
+            }
+        }
+        connection.flush();
+    }
+
+    /// Ends the drag once the pointer button is released, dropping the data onto the current
+    /// target if it accepts it.
+    pub fn handle_release(&self, connection: &impl XdndConnection, time: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.time = time;
+        if state.phase != DragPhase::Dragging {
+            return;
+        }
+        connection.ungrab_pointer();
+        state.released = true;
+        let awaiting_status = state
+            .target
+            .as_ref()
+            .is_some_and(|target| target.awaiting_status);
+        if awaiting_status {
+            state.deadline = Some(Instant::now() + DROP_TIMEOUT);
+        } else {
+            self.drop_or_cancel(connection, &mut state);
+        }
+        connection.flush();
+    }
+
+    /// Returns the outcome of a drag that ended without a drop, if it hasn't been reported yet.
+    /// Drags whose target hasn't answered in time are ended here as well, as rejected.
+    pub fn take_ended_event(&self, connection: &impl XdndConnection) -> Option<WindowEvent> {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            if let Some(target) = state.target.take() {
+                // Targets are only told to leave if the data hasn't been dropped onto them.
+                if state.phase == DragPhase::Dragging {
+                    self.send(connection, target.window, self.atoms.xdnd_leave, [0; 4]);
+                    connection.flush();
+                }
+            }
+            state.phase = DragPhase::Idle;
+            state.deadline = None;
+            state.ended = Some(false);
+        }
+        let ended = state.ended.take()?;
+        Some(WindowEvent::DragEnded { accepted: ended })
+    }
+
+    /// Returns how long until a target that hasn't answered is given up on, so that waiting for
+    /// events can stop in time to report it.
+    pub fn timeout(&self) -> Option<Duration> {
+        let deadline = self.state.lock().unwrap().deadline?;
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Handles a `ClientMessage` sent to the window by a drop target, returning the corresponding
+    /// `WindowEvent` if there is one. Other messages are ignored.
+    pub fn handle_client_message(
+        &self,
+        connection: &impl XdndConnection,
+        type_: u32,
+        data: [u32; 5],
+    ) -> Option<WindowEvent> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let target = state
+            .target
+            .as_mut()
+            .filter(|target| target.window == data[0])?;
+        if type_ == self.atoms.xdnd_status && state.phase == DragPhase::Dragging {
+            target.accepted = data[1] & 1 != 0;
+            target.awaiting_status = false;
+            if state.released {
+                self.drop_or_cancel(connection, state);
+            } else if let Some((position, time)) = target.pending_position.take() {
+                self.send_position(connection, target, position, time);
+            }
+            connection.flush();
+            let ended = state.ended.take()?;
+            Some(WindowEvent::DragEnded { accepted: ended })
+        } else if type_ == self.atoms.xdnd_finished && state.phase == DragPhase::Dropping {
+            // Targets only report whether they accepted the drop since version 5.
+            let accepted = target.version < 5 || data[1] & 1 != 0;
+            state.target = None;
+            state.phase = DragPhase::Idle;
+            state.deadline = None;
+            Some(WindowEvent::DragEnded { accepted })
+        } else {
+            None
+        }
+    }
+
+    /// Finds the innermost window under the given root window coordinates that supports XDND,
+    /// returning it along with the protocol version to use with it.
+    fn find_target(&self, connection: &impl XdndConnection, x: i16, y: i16) -> Option<(u32, u32)> {
+        let mut window = self.root_id;
+        loop {
+            window = connection.child_at(window, x, y)?;
+            if window == self.window_id {
+                return None;
+            }
+            let version = connection
+                .get_property(window, self.atoms.xdnd_aware, false)
+                .and_then(|bytes| {
+                    let b = bytes.get(..4)?;
+                    Some(u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                });
+            if let Some(version) = version {
+                return (version >= MIN_TARGET_VERSION)
+                    .then_some((window, version.min(XDND_VERSION)));
+            }
+        }
+    }
+
+    fn send_position(
+        &self,
+        connection: &impl XdndConnection,
+        target: &mut DragTarget,
+        position: u32,
+        time: u32,
+    ) {
+        target.awaiting_status = true;
+        let data = [0, position, time, self.atoms.xdnd_action_copy];
+        self.send(connection, target.window, self.atoms.xdnd_position, data);
+    }
+
+    /// Drops the data onto the current target if it accepted it, or cancels the drag otherwise.
+    fn drop_or_cancel(&self, connection: &impl XdndConnection, state: &mut DragState) {
+        match state.target.take() {
+            Some(target) if target.accepted => {
+                let data = [0, state.time, 0, 0];
+                self.send(connection, target.window, self.atoms.xdnd_drop, data);
+                state.target = Some(target);
+                state.phase = DragPhase::Dropping;
+                state.deadline = Some(Instant::now() + DROP_TIMEOUT);
+            }
+            target => {
+                if let Some(target) = target {
+                    self.send(connection, target.window, self.atoms.xdnd_leave, [0; 4]);
+                }
+                state.phase = DragPhase::Idle;
+                state.ended = Some(false);
+                state.deadline = None;
+            }
+        }
+    }
+
+    /// Sends an XDND message to `window`. Every message starts with the source window, followed
+    /// by `data`.
+    fn send(&self, connection: &impl XdndConnection, window: u32, type_: u32, data: [u32; 4]) {
+        let [a, b, c, d] = data;
+        connection.send_client_message(window, type_, [self.window_id, a, b, c, d]);
+    }
+}
+
+/// Extracts local file paths from a `text/uri-list`, ignoring comments and non-`file` URIs.
+pub fn parse_uri_list(data: &[u8]) -> Vec<PathBuf> {
+    data.split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .filter_map(|uri| {
+            let rest = uri.strip_prefix(b"file://")?;
+            // The host part is either empty or names this machine.
+            let path = &rest[rest.iter().position(|&b| b == b'/')?..];
+            Some(PathBuf::from(OsStr::from_bytes(&percent_decode(path))))
+        })
+        .collect()
+}
+
+/// Resolves relative paths against the current directory, since `file` URIs can only hold absolute
+/// paths. Returns `None` if the current directory is needed but can't be determined.
+fn absolute_paths(paths: Vec<PathBuf>) -> Option<Vec<PathBuf>> {
+    if paths.iter().all(|path| path.is_absolute()) {
+        return Some(paths);
+    }
+    let current_dir = std::env::current_dir().ok()?;
+    Some(paths.iter().map(|path| current_dir.join(path)).collect())
+}
+
+/// Builds a `text/uri-list` of `file` URIs for absolute local paths, percent-encoding any bytes
+/// that aren't allowed in URIs.
+pub fn encode_uri_list(paths: &[PathBuf]) -> Vec<u8> {
+    let mut data = vec![];
+    for path in paths {
+        data.extend_from_slice(b"file://");
+        for &byte in path.as_os_str().as_bytes() {
+            if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+                data.push(byte);
+            } else {
+                data.extend_from_slice(format!("%{:02X}", byte).as_bytes());
+            }
+        }
+        data.extend_from_slice(b"\r\n");
+    }
+    data
+}
+
+/// Decodes `%XX` escape sequences, leaving malformed ones as they are.
+fn percent_decode(data: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        let hex = data
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (data[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
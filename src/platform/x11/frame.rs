@@ -0,0 +1,88 @@
+//! A timer thread that paces `WindowEvent::Frame` events independently of the host's idle rate.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::event::WindowEvent;
+
+/// Periodically wakes the event source by sending a `ClientMessage` to its window, which is then
+/// converted into a `WindowEvent::Frame`.
+///
+/// Only one frame message is in flight at a time. If the editor is polling more slowly than the
+/// requested rate, frames are skipped rather than piling up in the event queue, and the `delta` of
+/// the next `Frame` reflects the actual time between frames.
+pub struct FrameTimer {
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    pending: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    start: Instant,
+    last_frame: Mutex<Duration>,
+}
+
+impl FrameTimer {
+    /// Starts a timer thread that calls `send_frame_message` every `interval`.
+    pub fn start(interval: Duration, send_frame_message: impl Fn() + Send + 'static) -> Self {
+        let stopped = Arc::new((Mutex::new(false), Condvar::new()));
+        let pending = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stopped = Arc::clone(&stopped);
+            let pending = Arc::clone(&pending);
+            std::thread::spawn(move || {
+                let (lock, condvar) = &*stopped;
+                // Intervals too long to be represented as an `Instant` never elapse.
+                let mut next_frame = Instant::now().checked_add(interval);
+                let mut is_stopped = lock.lock().unwrap();
+                while !*is_stopped {
+                    let now = Instant::now();
+                    let deadline = match next_frame {
+                        Some(deadline) => deadline,
+                        None => {
+                            is_stopped = condvar.wait(is_stopped).unwrap();
+                            continue;
+                        }
+                    };
+                    if now < deadline {
+                        is_stopped = condvar.wait_timeout(is_stopped, deadline - now).unwrap().0;
+                        continue;
+                    }
+                    if !pending.swap(true, Ordering::AcqRel) {
+                        send_frame_message();
+                    }
+                    // Missed deadlines are dropped rather than caught up on.
+                    next_frame = deadline.checked_add(interval).map(|next| next.max(now));
+                }
+            })
+        };
+        Self {
+            stopped,
+            pending,
+            thread: Some(thread),
+            start: Instant::now(),
+            last_frame: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Builds the `WindowEvent::Frame` for a frame message received by the event source, allowing
+    /// the timer to send the next one.
+    pub fn frame_event(&self) -> WindowEvent {
+        self.pending.store(false, Ordering::Release);
+        let timestamp = self.start.elapsed();
+        let mut last_frame = self.last_frame.lock().unwrap();
+        let delta = timestamp - *last_frame;
+        *last_frame = timestamp;
+        WindowEvent::Frame { timestamp, delta }
+    }
+}
+
+impl Drop for FrameTimer {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.stopped;
+        *lock.lock().unwrap() = true;
+        condvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
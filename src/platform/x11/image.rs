@@ -0,0 +1,295 @@
+//! Helpers for uploading CPU-rendered pixels to the X server.
+
+use std::sync::{Arc, Mutex};
+
+use super::shm::{is_local_display, ShmSegment};
+use crate::platform::{Image, PresentMethod, Rect};
+
+/// Size of the fixed part of a `PutImage` request, in bytes.
+const PUT_IMAGE_HEADER_BYTES: usize = 24;
+
+/// Above this many damaged regions, it's cheaper to upload their bounding box in one go than to
+/// issue a request for each.
+const MAX_DAMAGE_RECTS: usize = 16;
+
+/// The X server rejects requests above a certain size, so large images have to be uploaded over
+/// multiple `PutImage` requests. This splits a rectangle into bands of rows that each fit within
+/// `max_request_bytes`.
+pub fn row_bands(rect: Rect, max_request_bytes: usize) -> impl Iterator<Item = Rect> {
+    let row_bytes = (rect.width as usize * 4).max(1);
+    let rows_per_band = ((max_request_bytes - PUT_IMAGE_HEADER_BYTES) / row_bytes).max(1) as u32;
+    (rect.y..(rect.y + rect.height))
+        .step_by(rows_per_band as usize)
+        .map(move |y| Rect {
+            y,
+            height: rows_per_band.min(rect.y + rect.height - y),
+            ..rect
+        })
+}
+
+/// Encodes a `0x00RRGGBB` pixel as `ZPixmap` data for a 24 or 32 bit deep TrueColor visual, in
+/// the byte order expected by the X server.
+fn encode_pixel(pixel: u32, msb_first: bool) -> [u8; 4] {
+    if msb_first {
+        pixel.to_be_bytes()
+    } else {
+        pixel.to_le_bytes()
+    }
+}
+
+/// Encodes a rectangle of an image with `stride` pixels per row as tightly packed `ZPixmap` data.
+pub fn encode_rect(pixels: &[u32], stride: u32, rect: &Rect, msb_first: bool) -> Vec<u8> {
+    let mut data = Vec::with_capacity(rect.width as usize * rect.height as usize * 4);
+    for row in rect.y..(rect.y + rect.height) {
+        let start = (row * stride + rect.x) as usize;
+        for &pixel in &pixels[start..(start + rect.width as usize)] {
+            data.extend_from_slice(&encode_pixel(pixel, msb_first));
+        }
+    }
+    data
+}
+
+/// Encodes a rectangle of an image with `stride` pixels per row as `ZPixmap` data, written at the
+/// same position within `out`, which holds an image with the same layout.
+pub fn encode_rect_in_place(
+    out: &mut [u8],
+    pixels: &[u32],
+    stride: u32,
+    rect: &Rect,
+    msb_first: bool,
+) {
+    for row in rect.y..(rect.y + rect.height) {
+        let start = (row * stride + rect.x) as usize;
+        let end = start + rect.width as usize;
+        let out_row = out[(start * 4)..(end * 4)].chunks_exact_mut(4);
+        for (bytes, &pixel) in out_row.zip(&pixels[start..end]) {
+            bytes.copy_from_slice(&encode_pixel(pixel, msb_first));
+        }
+    }
+}
+
+/// Decodes `ZPixmap` data read back from a 24 or 32 bit deep TrueColor visual into an RGBA
+/// `Image`, or returns `None` if the data doesn't have the expected size.
+pub fn decode_image(data: &[u8], width: u32, height: u32, msb_first: bool) -> Option<Image> {
+    if data.len() != width as usize * height as usize * 4 {
+        return None;
+    }
+    let data = data
+        .chunks_exact(4)
+        .flat_map(|bytes| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            let pixel = if msb_first {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            };
+            let [_, r, g, b] = pixel.to_be_bytes();
+            [r, g, b, 0xff]
+        })
+        .collect();
+    Some(Image {
+        width,
+        height,
+        data,
+    })
+}
+
+/// Clips the given damaged rectangles to a `width` x `height` frame, discarding any that end up
+/// empty. Too many rectangles are merged into their bounding box.
+pub fn clip_damage(damage: &[Rect], width: u32, height: u32) -> Vec<Rect> {
+    let clipped: Vec<Rect> = damage
+        .iter()
+        .filter_map(|rect| {
+            let x_end = (rect.x.saturating_add(rect.width)).min(width);
+            let y_end = (rect.y.saturating_add(rect.height)).min(height);
+            if rect.x >= x_end || rect.y >= y_end {
+                None
+            } else {
+                Some(Rect {
+                    x: rect.x,
+                    y: rect.y,
+                    width: x_end - rect.x,
+                    height: y_end - rect.y,
+                })
+            }
+        })
+        .collect();
+    if clipped.len() <= MAX_DAMAGE_RECTS {
+        return clipped;
+    }
+    vec![bounding_box(&clipped)]
+}
+
+/// Returns the smallest rectangle containing all of the given ones, which mustn't be empty.
+fn bounding_box(rects: &[Rect]) -> Rect {
+    let x = rects.iter().map(|r| r.x).min().unwrap();
+    let y = rects.iter().map(|r| r.y).min().unwrap();
+    let x_end = rects
+        .iter()
+        .map(|r| r.x.saturating_add(r.width))
+        .max()
+        .unwrap();
+    let y_end = rects
+        .iter()
+        .map(|r| r.y.saturating_add(r.height))
+        .max()
+        .unwrap();
+    Rect {
+        x,
+        y,
+        width: x_end - x,
+        height: y_end - y,
+    }
+}
+
+/// Regions of a window that the X server has asked to be redrawn, shared between the window and
+/// its event source.
+#[derive(Clone, Default)]
+pub struct Exposures(Arc<Mutex<Vec<Rect>>>);
+
+impl Exposures {
+    /// Records an exposed region, reported by an `Expose` event.
+    ///
+    /// Editors that never call `present_pixels` never take the regions, so once there are more
+    /// than `clip_damage` would upload separately, they are merged into their bounding box to keep
+    /// the list from growing for the whole session.
+    pub fn add(&self, rect: Rect) {
+        let mut rects = self.0.lock().unwrap();
+        rects.push(rect);
+        if rects.len() > MAX_DAMAGE_RECTS {
+            *rects = vec![bounding_box(&rects)];
+        }
+    }
+
+    /// Returns all exposed regions recorded since the last call.
+    pub fn take(&self) -> Vec<Rect> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// Operations on an X11 window required for software presentation, implemented by each X11
+/// client library backend.
+pub trait ImageTarget {
+    /// Maximum size of a single request to the X server, in bytes.
+    fn maximum_request_bytes(&self) -> usize;
+    /// Whether the X server expects image data in big-endian byte order.
+    fn image_msb_first(&self) -> bool;
+    /// Draws `ZPixmap` data to `rect` of the window using `PutImage`.
+    fn put_image(&self, rect: &Rect, data: &[u8]);
+    /// Asks the X server to attach the shared memory segment with the given id, returning the
+    /// X11 id of the attached segment, or `None` if the MIT-SHM extension is unavailable.
+    fn shm_attach(&self, shmid: u32) -> Option<u32>;
+    /// Asks the X server to detach a segment previously attached with `shm_attach`.
+    fn shm_detach(&self, shmseg: u32);
+    /// Draws `rect` of a `total_width` x `total_height` `ZPixmap` image stored in an attached
+    /// segment to the same position in the window.
+    fn shm_put_image(&self, shmseg: u32, total_width: u32, total_height: u32, rect: &Rect);
+    /// Blocks until the X server has processed all previous requests.
+    fn sync(&self);
+    /// Sends all buffered requests to the X server.
+    fn flush(&self);
+}
+
+/// Software presentation state for a window. The presentation method is decided on first use.
+pub enum Presenter {
+    Uninitialized,
+    SharedMemory { segment: ShmSegment, shmseg: u32 },
+    PutImage,
+}
+
+impl Presenter {
+    /// Returns the method used to present pixels, deciding on one if necessary. A shared memory
+    /// segment large enough for `initial_size_xy` pixels is allocated if MIT-SHM is available.
+    pub fn method(
+        &mut self,
+        target: &impl ImageTarget,
+        initial_size_xy: (u32, u32),
+    ) -> PresentMethod {
+        if let Presenter::Uninitialized = self {
+            let size = initial_size_xy.0 as usize * initial_size_xy.1 as usize * 4;
+            *self = Self::attach(target, size);
+        }
+        match self {
+            Presenter::SharedMemory { .. } => PresentMethod::SharedMemory,
+            _ => PresentMethod::PutImage,
+        }
+    }
+
+    /// Draws the given rectangles of a `width` x `height` image to the same position in the
+    /// window. `damage` must already be clipped to the image bounds.
+    pub fn present(
+        &mut self,
+        target: &impl ImageTarget,
+        pixels: &[u32],
+        width: u32,
+        height: u32,
+        damage: &[Rect],
+    ) {
+        if damage.is_empty() {
+            return;
+        }
+
+        let size = width as usize * height as usize * 4;
+        self.method(target, (width, height));
+        if let Presenter::SharedMemory { segment, .. } = self {
+            if segment.size() < size {
+                self.release(target);
+                *self = Self::attach(target, size);
+            }
+        }
+
+        let msb_first = target.image_msb_first();
+        match self {
+            Presenter::SharedMemory { segment, shmseg } => {
+                // Only the damaged rectangles are read from the segment, so the rest of it doesn't
+                // need to be kept up to date.
+                for rect in damage {
+                    encode_rect_in_place(segment.data_mut(), pixels, width, rect, msb_first);
+                    target.shm_put_image(*shmseg, width, height, rect);
+                }
+                // The round trip guarantees that the X server is done reading the segment, so it
+                // can be safely overwritten with the next frame.
+                target.sync();
+            }
+            _ => {
+                let max_request_bytes = target.maximum_request_bytes();
+                for band in damage
+                    .iter()
+                    .flat_map(|rect| row_bands(*rect, max_request_bytes))
+                {
+                    target.put_image(&band, &encode_rect(pixels, width, &band, msb_first));
+                }
+                target.flush();
+            }
+        }
+    }
+
+    /// Detaches any shared memory segment from the X server. The `Presenter` reverts to its
+    /// uninitialized state.
+    pub fn release(&mut self, target: &impl ImageTarget) {
+        if let Presenter::SharedMemory { shmseg, .. } = self {
+            target.shm_detach(*shmseg);
+            target.flush();
+        }
+        *self = Presenter::Uninitialized;
+    }
+
+    /// MIT-SHM only works if the X server can access this process's memory, so it is only
+    /// attempted for local displays. Any failure falls back to `PutImage`.
+    fn attach(target: &impl ImageTarget, size: usize) -> Self {
+        if !is_local_display() {
+            return Presenter::PutImage;
+        }
+        let segment = match ShmSegment::new(size) {
+            Some(segment) => segment,
+            None => return Presenter::PutImage,
+        };
+        match target.shm_attach(segment.id()) {
+            Some(shmseg) => {
+                segment.mark_for_removal();
+                Presenter::SharedMemory { segment, shmseg }
+            }
+            None => Presenter::PutImage,
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Composition of text through an X input method (XIM) server, for typing text that doesn't map
+//! directly to keys, such as Chinese, Japanese or Korean.
+//!
+//! The XIM protocol is spoken by the `xim` crate over a separate `x11rb` connection, regardless of
+//! the client library used by the platform module. Key events received by the event source are
+//! forwarded to the server named by the `XMODIFIERS` environment variable (e.g. `@im=ibus`), which
+//! answers with the text being composed (the "preedit") and, once composition is finished, the
+//! text to insert. The editor draws the preedit itself, since the "on-the-spot" input style is
+//! requested.
+//!
+//! See <https://www.x.org/releases/X11R7.6/doc/libX11/specs/XIM/xim.html> for the protocol.
+
+use std::collections::VecDeque;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{KeyPressEvent, KEY_RELEASE_EVENT};
+use x11rb::rust_connection::RustConnection;
+use xim::x11rb::{HasConnection, X11rbClient};
+use xim::{
+    AttributeName, CaretDirection, CaretStyle, Client, ClientError, ClientHandler, Feedback,
+    ForwardEventFlag, InputStyle, Point, PreeditDrawStatus,
+};
+
+use super::keyboard::HeldKeys;
+use crate::event::WindowEvent;
+
+type XimClient = X11rbClient<RustConnection>;
+
+/// The input context of an editor window. Shared between the window, which places the input
+/// method's candidate window, and the event source, which forwards key presses and emits the
+/// composed text.
+///
+/// Does nothing if no input method server is available, or once the connection to it fails.
+#[derive(Clone)]
+pub struct InputMethod(Arc<Mutex<Option<Session>>>);
+
+struct Session {
+    client: XimClient,
+    handler: Handler,
+}
+
+impl InputMethod {
+    /// Connects to the input method server, if there is one. The input context for `window_id` is
+    /// created asynchronously, as the event source processes the server's answers.
+    pub fn connect(window_id: u32) -> Self {
+        Self(Arc::new(Mutex::new(Session::connect(window_id))))
+    }
+
+    /// Returns an input context that never connects, for windows that don't take text input.
+    pub fn disconnected() -> Self {
+        Self(Arc::new(Mutex::new(None)))
+    }
+
+    /// Moves the spot near which the server places its candidate window, in pixels relative to
+    /// the top-left of the window. Applies to the input context once it has been created, if it
+    /// doesn't exist yet.
+    pub fn set_spot(&self, x: i16, y: i16) {
+        self.with_session(|session| {
+            session.handler.spot = (x, y);
+            let (input_method_id, input_context_id) = match session.handler.input_context {
+                Some(ids) => ids,
+                None => return Ok(()),
+            };
+            let attributes = session
+                .client
+                .build_ic_attributes()
+                .nested_list(AttributeName::PreeditAttributes, |b| {
+                    b.push(AttributeName::SpotLocation, Point { x, y });
+                })
+                .build();
+            session
+                .client
+                .set_ic_values(input_method_id, input_context_id, attributes)
+        });
+    }
+
+    /// Forwards a key press or release to the server, returning `false` if there is no input
+    /// context to forward it to. Keys the server doesn't consume are sent back, and returned by
+    /// `poll_event`. Releases are forwarded as well, so that they are returned after their presses.
+    pub fn forward_key(&self, event: &KeyPressEvent) -> bool {
+        let mut forwarded = false;
+        self.with_session(|session| {
+            let (input_method_id, input_context_id) = match session.handler.input_context {
+                Some(ids) => ids,
+                None => return Ok(()),
+            };
+            forwarded = true;
+            session.client.forward_event(
+                input_method_id,
+                input_context_id,
+                ForwardEventFlag::empty(),
+                event,
+            )
+        });
+        forwarded
+    }
+
+    /// Processes the server's messages, and returns the next composition event or key event sent
+    /// back by the server, if any. `keys` tracks the keys held down, like for key events that
+    /// aren't forwarded.
+    pub fn poll_event(&self, keys: &HeldKeys) -> Option<WindowEvent> {
+        loop {
+            let mut event = None;
+            self.with_session(|session| {
+                let Session { client, handler } = session;
+                while let Some(x11_event) = client.conn().poll_for_event()? {
+                    client.filter_event(&x11_event, handler)?;
+                }
+                event = handler.events.pop_front();
+                Ok(())
+            });
+            // Releases of keys whose press was consumed are skipped.
+            match event? {
+                WindowEvent::KeyPress { keycode, .. } => return Some(keys.press(keycode as u8)),
+                WindowEvent::KeyRelease { keycode } => {
+                    if let Some(event) = keys.release(keycode as u8) {
+                        return Some(event);
+                    }
+                }
+                event => return Some(event),
+            }
+        }
+    }
+
+    /// Returns the file descriptor of the connection to the server, which becomes readable when
+    /// it sends a message.
+    pub fn raw_fd(&self) -> Option<RawFd> {
+        let session = self.0.lock().unwrap();
+        session
+            .as_ref()
+            .map(|session| session.client.conn().stream().as_raw_fd())
+    }
+
+    /// Runs `f` on the session, if there is one. Input methods are optional, so errors aren't
+    /// reported; the session is closed instead, after which composition is no longer possible.
+    fn with_session(&self, f: impl FnOnce(&mut Session) -> Result<(), ClientError>) {
+        let mut session = self.0.lock().unwrap();
+        if let Some(s) = session.as_mut() {
+            if f(s).is_err() {
+                *session = None;
+            }
+        }
+    }
+}
+
+impl Session {
+    fn connect(window_id: u32) -> Option<Self> {
+        // Avoid opening a connection if no server is configured at all.
+        std::env::var_os("XMODIFIERS")?;
+        let (connection, screen_num) = RustConnection::connect(None).ok()?;
+        let client = X11rbClient::init(connection, screen_num, None).ok()?;
+        Some(Self {
+            client,
+            handler: Handler {
+                window_id,
+                input_context: None,
+                spot: (0, 0),
+                preedit: Vec::new(),
+                events: VecDeque::new(),
+            },
+        })
+    }
+}
+
+/// Walks the server through creating an input context, and translates its composition callbacks
+/// into `WindowEvent`s.
+struct Handler {
+    window_id: u32,
+    /// The ids of the opened input method and of the window's input context, once created.
+    input_context: Option<(u16, u16)>,
+    spot: (i16, i16),
+    /// The text being composed. The server refers to positions within it in characters.
+    preedit: Vec<char>,
+    events: VecDeque<WindowEvent>,
+}
+
+impl Handler {
+    /// Emits the current preedit, with the caret at the given character position.
+    fn push_preedit(&mut self, caret: i32) {
+        let caret = (caret.max(0) as usize).min(self.preedit.len());
+        let text: String = self.preedit.iter().collect();
+        let cursor = self.preedit[..caret].iter().map(|c| c.len_utf8()).sum();
+        self.events
+            .push_back(WindowEvent::ImePreedit { text, cursor });
+    }
+}
+
+impl ClientHandler<XimClient> for Handler {
+    fn handle_connect(&mut self, client: &mut XimClient) -> Result<(), ClientError> {
+        client.open(&locale())
+    }
+
+    fn handle_open(
+        &mut self,
+        client: &mut XimClient,
+        input_method_id: u16,
+    ) -> Result<(), ClientError> {
+        client.get_im_values(input_method_id, &[AttributeName::QueryInputStyle])
+    }
+
+    /// The input styles supported by the server are ignored, since only the on-the-spot style
+    /// lets the editor draw the preedit itself.
+    fn handle_get_im_values(
+        &mut self,
+        client: &mut XimClient,
+        input_method_id: u16,
+        _attributes: xim::AHashMap<AttributeName, Vec<u8>>,
+    ) -> Result<(), ClientError> {
+        let (x, y) = self.spot;
+        let attributes = client
+            .build_ic_attributes()
+            .push(
+                AttributeName::InputStyle,
+                InputStyle::PREEDIT_CALLBACKS | InputStyle::STATUS_NOTHING,
+            )
+            .push(AttributeName::ClientWindow, self.window_id)
+            .push(AttributeName::FocusWindow, self.window_id)
+            .nested_list(AttributeName::PreeditAttributes, |b| {
+                b.push(AttributeName::SpotLocation, Point { x, y });
+            })
+            .build();
+        client.create_ic(input_method_id, attributes)
+    }
+
+    fn handle_create_ic(
+        &mut self,
+        client: &mut XimClient,
+        input_method_id: u16,
+        input_context_id: u16,
+    ) -> Result<(), ClientError> {
+        self.input_context = Some((input_method_id, input_context_id));
+        client.set_focus(input_method_id, input_context_id)
+    }
+
+    fn handle_destroy_ic(
+        &mut self,
+        _client: &mut XimClient,
+        _input_method_id: u16,
+        _input_context_id: u16,
+    ) -> Result<(), ClientError> {
+        self.input_context = None;
+        Ok(())
+    }
+
+    fn handle_disconnect(&mut self) {
+        self.input_context = None;
+    }
+
+    fn handle_commit(
+        &mut self,
+        _client: &mut XimClient,
+        _input_method_id: u16,
+        _input_context_id: u16,
+        text: &str,
+    ) -> Result<(), ClientError> {
+        self.events
+            .push_back(WindowEvent::ImeCommit(text.to_owned()));
+        Ok(())
+    }
+
+    /// Whether a key press is a repeat, or whether a release has a reported press, is only known
+    /// once it is polled.
+    fn handle_forward_event(
+        &mut self,
+        _client: &mut XimClient,
+        _input_method_id: u16,
+        _input_context_id: u16,
+        _flag: ForwardEventFlag,
+        event: KeyPressEvent,
+    ) -> Result<(), ClientError> {
+        let keycode = event.detail.into();
+        // The most significant bit is set for events sent by other clients.
+        let event = if event.response_type & 0x7f == KEY_RELEASE_EVENT {
+            WindowEvent::KeyRelease { keycode }
+        } else {
+            WindowEvent::KeyPress {
+                keycode,
+                repeat: false,
+            }
+        };
+        self.events.push_back(event);
+        Ok(())
+    }
+
+    fn handle_preedit_start(
+        &mut self,
+        _client: &mut XimClient,
+        _input_method_id: u16,
+        _input_context_id: u16,
+    ) -> Result<(), ClientError> {
+        self.preedit.clear();
+        Ok(())
+    }
+
+    /// Replaces `chg_len` characters of the preedit, starting at `chg_first`, with the new text.
+    fn handle_preedit_draw(
+        &mut self,
+        _client: &mut XimClient,
+        _input_method_id: u16,
+        _input_context_id: u16,
+        caret: i32,
+        chg_first: i32,
+        chg_len: i32,
+        status: PreeditDrawStatus,
+        preedit_string: &str,
+        _feedbacks: Vec<Feedback>,
+    ) -> Result<(), ClientError> {
+        let first = (chg_first.max(0) as usize).min(self.preedit.len());
+        let end = (first + chg_len.max(0) as usize).min(self.preedit.len());
+        let text = if status.contains(PreeditDrawStatus::NO_STRING) {
+            ""
+        } else {
+            preedit_string
+        };
+        self.preedit.splice(first..end, text.chars());
+        self.push_preedit(caret);
+        Ok(())
+    }
+
+    /// Only absolute caret positions are followed. Relative movements would require knowing
+    /// where word and line boundaries are within the editor's text.
+    fn handle_preedit_caret(
+        &mut self,
+        _client: &mut XimClient,
+        _input_method_id: u16,
+        _input_context_id: u16,
+        position: &mut i32,
+        direction: CaretDirection,
+        _style: CaretStyle,
+    ) -> Result<(), ClientError> {
+        if direction == CaretDirection::AbsolutePosition {
+            self.push_preedit(*position);
+        }
+        Ok(())
+    }
+
+    fn handle_preedit_done(
+        &mut self,
+        _client: &mut XimClient,
+        _input_method_id: u16,
+        _input_context_id: u16,
+    ) -> Result<(), ClientError> {
+        if !self.preedit.is_empty() {
+            self.preedit.clear();
+            self.push_preedit(0);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the locale that text is composed for, e.g. `ja_JP`, from the usual environment
+/// variables, without the encoding or modifier. Text is always exchanged as UTF-8 regardless.
+fn locale() -> String {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    match locale {
+        "" | "C" | "POSIX" => "en_US".to_owned(),
+        locale => locale.to_owned(),
+    }
+}
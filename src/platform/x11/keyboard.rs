@@ -0,0 +1,51 @@
+//! Tracking of held keys, to tell auto-repeated key presses apart from new ones.
+//!
+//! By default, X11 reports a held key as a series of releases and presses that can't be told apart
+//! from the user tapping the key. The platform modules enable XKB's "detectable auto-repeat" on
+//! their connection instead, so that only the presses are repeated, and the key is released once.
+//! A press of a key that is already held down is then a repeat.
+
+use std::sync::Mutex;
+
+use crate::event::WindowEvent;
+
+/// The keys held down within the window, by keycode.
+pub struct HeldKeys(Mutex<[bool; 256]>);
+
+impl Default for HeldKeys {
+    fn default() -> Self {
+        Self(Mutex::new([false; 256]))
+    }
+}
+
+impl HeldKeys {
+    /// Returns the `KeyPress` event for a press of `keycode`, which is a repeat if the key is
+    /// already held down.
+    pub fn press(&self, keycode: u8) -> WindowEvent {
+        let mut held = self.0.lock().unwrap();
+        let repeat = std::mem::replace(&mut held[keycode as usize], true);
+        WindowEvent::KeyPress {
+            keycode: keycode.into(),
+            repeat,
+        }
+    }
+
+    /// Returns the `KeyRelease` event for a release of `keycode`, or `None` if its press wasn't
+    /// reported, e.g. because an input method consumed it, or because it happened before the
+    /// window had the keyboard focus.
+    pub fn release(&self, keycode: u8) -> Option<WindowEvent> {
+        let mut held = self.0.lock().unwrap();
+        if !std::mem::replace(&mut held[keycode as usize], false) {
+            return None;
+        }
+        Some(WindowEvent::KeyRelease {
+            keycode: keycode.into(),
+        })
+    }
+
+    /// Forgets all held keys. Called when the window loses the keyboard focus, since the keys'
+    /// releases are then sent to another window.
+    pub fn clear(&self) {
+        *self.0.lock().unwrap() = [false; 256];
+    }
+}
@@ -0,0 +1,17 @@
+//! X11 logic shared between the `unix` and `x11rb` platform modules, independent of the client
+//! library used to talk to the X server.
+
+pub mod atoms;
+pub mod capture;
+pub mod connection;
+pub mod dnd;
+pub mod frame;
+pub mod image;
+#[cfg(feature = "ime")]
+pub mod ime;
+pub mod keyboard;
+pub mod popup;
+pub mod selection;
+pub mod shm;
+pub mod wait;
+pub mod xembed;
@@ -0,0 +1,67 @@
+//! Popup windows, such as menus and tooltips, which are placed above all other windows rather than
+//! inside the host's window, so that they aren't clipped to the editor's bounds.
+//!
+//! Popups are override-redirect top-level windows, which window managers neither decorate nor
+//! move. Like in other toolkits, a popup grabs the pointer while it is open, so that clicks outside
+//! of it can be noticed and reported as `WindowEvent::PopupDismissed`.
+
+use std::sync::Mutex;
+
+use super::connection::X11Connection;
+use crate::event::WindowEvent;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GrabState {
+    /// The pointer hasn't been grabbed yet.
+    Pending,
+    Grabbed,
+    /// The popup was dismissed, and the grab released.
+    Dismissed,
+}
+
+/// The pointer grab of a popup's event source.
+pub struct PopupGrab {
+    window_id: u32,
+    size_xy: (i32, i32),
+    state: Mutex<GrabState>,
+}
+
+impl PopupGrab {
+    pub fn new(window_id: u32, size_xy: (i32, i32)) -> Self {
+        Self {
+            window_id,
+            size_xy,
+            state: Mutex::new(GrabState::Pending),
+        }
+    }
+
+    /// Grabs the pointer for the popup, unless it is already grabbed. Grabbing fails while another
+    /// client holds a grab, such as the editor's implicit grab while the button press that opened
+    /// the popup is held, so this is retried each time the event source is polled.
+    pub fn ensure_grabbed(&self, connection: &impl X11Connection) {
+        let mut state = self.state.lock().unwrap();
+        if *state == GrabState::Pending && connection.grab_pointer(self.window_id, false) {
+            *state = GrabState::Grabbed;
+        }
+    }
+
+    /// Returns `PopupDismissed` if a button was pressed outside of the popup, at coordinates
+    /// relative to its top-left, and releases the grab. Other presses are left to the caller.
+    pub fn handle_press(
+        &self,
+        connection: &impl X11Connection,
+        x: i16,
+        y: i16,
+    ) -> Option<WindowEvent> {
+        let mut state = self.state.lock().unwrap();
+        let (x, y) = (i32::from(x), i32::from(y));
+        let inside = (0..self.size_xy.0).contains(&x) && (0..self.size_xy.1).contains(&y);
+        if *state != GrabState::Grabbed || inside {
+            return None;
+        }
+        *state = GrabState::Dismissed;
+        connection.ungrab_pointer();
+        connection.flush();
+        Some(WindowEvent::PopupDismissed)
+    }
+}
@@ -0,0 +1,189 @@
+//! Ownership and conversion of X11 selections, used for the clipboard.
+//!
+//! See the ICCCM (<https://x.org/releases/X11R7.6/doc/xorg-docs/specs/ICCCM/icccm.html>) for how
+//! clients exchange data through selections.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::atoms::Atoms;
+use super::connection::X11Connection;
+
+/// The predefined `ATOM` atom, which is the type of the `TARGETS` conversion.
+pub const ATOM_ATOM: u32 = 4;
+
+/// The predefined `STRING` atom, for Latin-1 encoded text.
+pub const ATOM_STRING: u32 = 31;
+
+/// How long to wait for another client to convert a selection before giving up.
+const CONVERSION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Operations on the X server required to own selections, implemented by each X11 client library
+/// backend.
+pub trait SelectionConnection: X11Connection {
+    /// Makes `owner` the owner of `selection`, so that other clients' requests for its contents
+    /// are sent to it.
+    fn set_selection_owner(&self, owner: u32, selection: u32, time: u32);
+    /// Tells `requestor` that a conversion has finished, storing the result in `property`, or that
+    /// it failed if `property` is `0`.
+    fn send_selection_notify(
+        &self,
+        requestor: u32,
+        selection: u32,
+        target: u32,
+        property: u32,
+        time: u32,
+    );
+    /// Maximum size of a single request to the X server, in bytes.
+    fn maximum_request_bytes(&self) -> usize;
+}
+
+/// Size of a `ChangeProperty` request without its data, in bytes.
+const CHANGE_PROPERTY_HEADER_BYTES: usize = 24;
+
+/// Data offered through a selection, as one byte string per target.
+pub type Targets = Vec<(u32, Vec<u8>)>;
+
+/// Data offered to other clients through the selections owned by the window, keyed by selection
+/// and then by target. Filled in by the window, and read by the event source as requests arrive.
+#[derive(Clone, Default)]
+pub struct Selections(Arc<Mutex<HashMap<u32, Targets>>>);
+
+impl Selections {
+    /// Replaces the data offered through `selection`.
+    pub fn offer(&self, selection: u32, targets: Targets) {
+        self.0.lock().unwrap().insert(selection, targets);
+    }
+
+    /// Returns the data offered through `selection` as `target`, if any.
+    pub fn get(&self, selection: u32, target: u32) -> Option<Vec<u8>> {
+        let selections = self.0.lock().unwrap();
+        selections
+            .get(&selection)?
+            .iter()
+            .find(|(t, _)| *t == target)
+            .map(|(_, data)| data.clone())
+    }
+
+    fn targets(&self, selection: u32) -> Option<Vec<u32>> {
+        let selections = self.0.lock().unwrap();
+        Some(
+            selections
+                .get(&selection)?
+                .iter()
+                .map(|(t, _)| *t)
+                .collect(),
+        )
+    }
+}
+
+/// A `SelectionRequest` event, sent to the owner of a selection when another client wants its
+/// contents.
+pub struct SelectionRequest {
+    pub requestor: u32,
+    pub selection: u32,
+    pub target: u32,
+    pub property: u32,
+    pub time: u32,
+}
+
+/// Answers a `SelectionRequest` with the data offered through the requested selection. The
+/// `TARGETS` target lists every offered target. Unknown selections and targets are refused.
+///
+/// Data is stored in a single property, so incremental transfers of very large data aren't
+/// supported. Conversions to data that doesn't fit in a single request are refused instead.
+pub fn answer_request(
+    connection: &impl SelectionConnection,
+    atoms: &Atoms,
+    selections: &Selections,
+    request: SelectionRequest,
+) {
+    // Obsolete clients don't name a property, in which case the target is used.
+    let property = if request.property == 0 {
+        request.target
+    } else {
+        request.property
+    };
+    let answered = if request.target == atoms.targets {
+        selections
+            .targets(request.selection)
+            .map(|targets| {
+                let mut atoms_list = vec![atoms.targets];
+                atoms_list.extend(targets);
+                connection.set_property32(request.requestor, property, ATOM_ATOM, &atoms_list);
+            })
+            .is_some()
+    } else {
+        selections
+            .get(request.selection, request.target)
+            .filter(|data| {
+                data.len() <= connection.maximum_request_bytes() - CHANGE_PROPERTY_HEADER_BYTES
+            })
+            .map(|data| {
+                connection.set_property8(request.requestor, property, request.target, &data);
+            })
+            .is_some()
+    };
+    connection.send_selection_notify(
+        request.requestor,
+        request.selection,
+        request.target,
+        if answered { property } else { 0 },
+        request.time,
+    );
+    connection.flush();
+}
+
+/// Encodes text as `UTF8_STRING`, `text/plain;charset=utf-8` and, if possible, Latin-1 `STRING`
+/// targets, for offering it through a selection.
+pub fn text_targets(atoms: &Atoms, text: &str) -> Targets {
+    let mut targets = vec![
+        (atoms.utf8_string, text.as_bytes().to_vec()),
+        (atoms.text_plain_utf8, text.as_bytes().to_vec()),
+    ];
+    let latin1: Option<Vec<u8>> = text.chars().map(|c| u8::try_from(c).ok()).collect();
+    if let Some(latin1) = latin1 {
+        targets.push((ATOM_STRING, latin1));
+    }
+    targets
+}
+
+/// Operations required to read selections owned by other clients, implemented by each X11 client
+/// library backend.
+///
+/// Readers use their own connection and window, so that waiting for the owner's answer doesn't
+/// consume events meant for the event source.
+pub trait SelectionReader {
+    /// Asks the owner of `selection` to convert it to `target`, storing the result in `property`
+    /// of the reader's window. Waits for the owner's answer until `deadline`, returning `false` if
+    /// the conversion failed or timed out.
+    fn convert_selection(
+        &self,
+        selection: u32,
+        target: u32,
+        property: u32,
+        deadline: Instant,
+    ) -> bool;
+    /// Reads and deletes a property of the reader's window, returning its type and value.
+    fn take_property(&self, property: u32) -> Option<(u32, Vec<u8>)>;
+}
+
+/// Reads the contents of `selection` as text, preferring UTF-8 and falling back to Latin-1.
+pub fn read_text(reader: &impl SelectionReader, atoms: &Atoms, selection: u32) -> Option<String> {
+    let deadline = Instant::now() + CONVERSION_TIMEOUT;
+    for &target in &[atoms.utf8_string, ATOM_STRING] {
+        if !reader.convert_selection(selection, target, atoms.selection_property, deadline) {
+            continue;
+        }
+        let (type_, data) = reader.take_property(atoms.selection_property)?;
+        return match type_ {
+            // Large data is sent incrementally, which isn't supported.
+            t if t == atoms.incr => None,
+            ATOM_STRING => Some(data.iter().map(|&b| char::from(b)).collect()),
+            _ => Some(String::from_utf8_lossy(&data).into_owned()),
+        };
+    }
+    None
+}
@@ -0,0 +1,76 @@
+//! System V shared memory segments, used to upload images with the MIT-SHM extension.
+
+/// A shared memory segment, attached to this process's address space.
+pub struct ShmSegment {
+    id: i32,
+    addr: *mut u8,
+    size: usize,
+}
+
+/// The segment is only accessed through `&mut self`.
+unsafe impl Send for ShmSegment {}
+
+impl ShmSegment {
+    /// Allocates a new segment of `size` bytes, accessible only to the current user.
+    pub fn new(size: usize) -> Option<Self> {
+        let id = unsafe { libc::shmget(libc::IPC_PRIVATE, size.max(1), libc::IPC_CREAT | 0o600) };
+        if id == -1 {
+            return None;
+        }
+        let addr = unsafe { libc::shmat(id, std::ptr::null(), 0) };
+        if addr as isize == -1 {
+            unsafe { libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut()) };
+            return None;
+        }
+        Some(Self {
+            id,
+            addr: addr as *mut u8,
+            size,
+        })
+    }
+
+    /// The system-wide id of the segment, used to attach it to the X server.
+    pub fn id(&self) -> u32 {
+        self.id as u32
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.addr, self.size) }
+    }
+
+    /// Marks the segment to be destroyed once every process has detached from it, so that it
+    /// isn't leaked if this process exits abruptly. This must be called only after the X server
+    /// has attached the segment.
+    pub fn mark_for_removal(&self) {
+        unsafe { libc::shmctl(self.id, libc::IPC_RMID, std::ptr::null_mut()) };
+    }
+}
+
+impl Drop for ShmSegment {
+    fn drop(&mut self) {
+        unsafe {
+            libc::shmdt(self.addr as *const libc::c_void);
+            libc::shmctl(self.id, libc::IPC_RMID, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Returns `true` if the `DISPLAY` environment variable refers to an X server on this machine,
+/// connected over a Unix domain socket. TCP connections, including `localhost` (which is commonly
+/// used for SSH forwarding), are treated as remote.
+pub fn is_local_display() -> bool {
+    match std::env::var("DISPLAY") {
+        Ok(display) => match display.rfind(':') {
+            Some(colon) => {
+                let host = &display[..colon];
+                host.is_empty() || host == "unix" || host.starts_with('/')
+            }
+            None => false,
+        },
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,38 @@
+//! Blocking on the X server connection until events arrive.
+
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+/// The `EditorWindow` shares the event source's connection and may be used from another thread,
+/// e.g. to present frames from a render thread. X11 client libraries may read incoming events into
+/// their own queue while waiting for replies there, which doesn't make the socket readable, so
+/// waits are cut short at this interval to check the queue again.
+const MAX_WAIT: Duration = Duration::from_millis(100);
+
+/// Blocks until `fd` is readable or `timeout` has elapsed, whichever comes first. Returns early
+/// if interrupted by a signal, or after `MAX_WAIT`.
+pub fn wait_until_readable(fd: RawFd, timeout: Option<Duration>) {
+    wait_until_any_readable(&[fd], timeout)
+}
+
+/// Like `wait_until_readable`, but returns as soon as any of `fds` is readable.
+pub fn wait_until_any_readable(fds: &[RawFd], timeout: Option<Duration>) {
+    let timeout = timeout.map_or(MAX_WAIT, |timeout| timeout.min(MAX_WAIT));
+    let mut pollfds: Vec<_> = fds
+        .iter()
+        .map(|&fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+    // Round up, so that short timeouts don't turn into busy loops.
+    let timeout_ms = timeout.as_micros().div_ceil(1000) as libc::c_int;
+    unsafe {
+        libc::poll(
+            pollfds.as_mut_ptr(),
+            pollfds.len() as libc::nfds_t,
+            timeout_ms,
+        )
+    };
+}
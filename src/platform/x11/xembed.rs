@@ -0,0 +1,102 @@
+//! The client side of the XEmbed protocol, which some hosts (e.g. LV2 hosts wrapping plugin UIs in
+//! a `GtkSocket`) use to embed the editor's window and manage its keyboard focus.
+//!
+//! Embedders keep the X input focus on their own window, and tell the embedded window that it has
+//! the focus with messages instead, forwarding key events to it. The editor asks for the focus when
+//! it is clicked.
+//!
+//! Embedders read `_XEMBED_INFO` when the window is reparented or mapped into them, so the windows
+//! set it before being mapped. Only the handling of the messages can be switched on and off.
+//!
+//! See <https://specifications.freedesktop.org/xembed-spec/xembed-spec-latest.html> for the
+//! protocol itself.
+
+use std::sync::Mutex;
+
+use super::atoms::Atoms;
+use super::connection::X11Connection;
+use crate::event::WindowEvent;
+
+/// The version of the XEmbed protocol implemented here.
+const XEMBED_VERSION: u32 = 0;
+/// `_XEMBED_INFO` flag asking the embedder to map the window.
+const XEMBED_MAPPED: u32 = 1;
+
+/// The value of the `_XEMBED_INFO` property of embedded windows, of type `_XEMBED_INFO`.
+pub const XEMBED_INFO: [u32; 2] = [XEMBED_VERSION, XEMBED_MAPPED];
+
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+const XEMBED_REQUEST_FOCUS: u32 = 3;
+const XEMBED_FOCUS_IN: u32 = 4;
+const XEMBED_FOCUS_OUT: u32 = 5;
+
+#[derive(Default)]
+struct XembedState {
+    enabled: bool,
+    /// The window that the editor is embedded into, once the embedder has announced itself.
+    embedder: Option<u32>,
+    focused: bool,
+}
+
+/// The XEmbed state of the window.
+pub struct XembedClient {
+    atoms: Atoms,
+    state: Mutex<XembedState>,
+}
+
+impl XembedClient {
+    pub fn new(atoms: Atoms) -> Self {
+        Self {
+            atoms,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Starts or stops handling XEmbed messages.
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.enabled != enabled {
+            *state = XembedState {
+                enabled,
+                ..XembedState::default()
+            };
+        }
+    }
+
+    /// Handles a 32 bit `ClientMessage` sent to the window, returning `Focused` when the
+    /// embedder hands the focus to or away from the window. Messages that aren't part of the
+    /// XEmbed protocol are ignored.
+    pub fn handle_client_message(&self, type_: u32, data: [u32; 5]) -> Option<WindowEvent> {
+        let mut state = self.state.lock().unwrap();
+        if type_ != self.atoms.xembed || !state.enabled {
+            return None;
+        }
+        let focused = match data[1] {
+            XEMBED_EMBEDDED_NOTIFY => {
+                state.embedder = Some(data[3]);
+                return None;
+            }
+            XEMBED_FOCUS_IN => true,
+            XEMBED_FOCUS_OUT => false,
+            _ => return None,
+        };
+        if std::mem::replace(&mut state.focused, focused) == focused {
+            return None;
+        }
+        Some(WindowEvent::Focused(focused))
+    }
+
+    /// Asks the embedder for the focus when a mouse button is pressed within the window, unless
+    /// the window already has it.
+    pub fn handle_press(&self, connection: &impl X11Connection, time: u32) {
+        let state = self.state.lock().unwrap();
+        if let (true, false, Some(embedder)) = (state.enabled, state.focused, state.embedder) {
+            connection.send_client_message(
+                embedder,
+                self.atoms.xembed,
+                [time, XEMBED_REQUEST_FOCUS, 0, 0, 0],
+            );
+            connection.flush();
+        }
+    }
+}
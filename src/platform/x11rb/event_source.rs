@@ -0,0 +1,501 @@
+//! Provides a source for window events on Unix platforms, using `x11rb`.
+
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{
+    AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask, GrabMode, GrabStatus, PropMode,
+    SelectionNotifyEvent, SELECTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+use super::window::EditorWindowImpl;
+use crate::event::WindowEvent;
+use crate::platform::x11::atoms::Atoms;
+use crate::platform::x11::capture::PointerCapture;
+use crate::platform::x11::connection::X11Connection;
+use crate::platform::x11::dnd::{DragSource, DropTarget, XdndConnection};
+use crate::platform::x11::frame::FrameTimer;
+use crate::platform::x11::image::Exposures;
+#[cfg(feature = "ime")]
+use crate::platform::x11::ime::InputMethod;
+use crate::platform::x11::keyboard::HeldKeys;
+use crate::platform::x11::popup::PopupGrab;
+use crate::platform::x11::selection::{
+    answer_request, SelectionConnection, SelectionRequest, Selections,
+};
+#[cfg(feature = "ime")]
+use crate::platform::x11::wait::wait_until_any_readable;
+use crate::platform::x11::wait::wait_until_readable;
+use crate::platform::x11::xembed::XembedClient;
+use crate::platform::{DragData, EventSourceBackend, Rect, WakeFn};
+
+pub(in crate::platform) struct EventSourceImpl {
+    connection: Arc<RustConnection>,
+    size_xy: (i32, i32),
+    exposures: Exposures,
+    window_id: u32,
+    root_id: u32,
+    atoms: Atoms,
+    frame_timer: Mutex<Option<FrameTimer>>,
+    drop_target: DropTarget,
+    drag_source: DragSource,
+    selections: Selections,
+    keys: HeldKeys,
+    capture: PointerCapture,
+    xembed: XembedClient,
+    /// Set if the window is a popup.
+    popup: Option<PopupGrab>,
+    #[cfg(feature = "ime")]
+    ime: InputMethod,
+}
+
+impl EventSourceBackend for EventSourceImpl {
+    fn new(window: &EditorWindowImpl, size_xy: (i32, i32)) -> Self {
+        let atoms = window.atoms;
+        Self {
+            connection: Arc::clone(&window.connection),
+            size_xy,
+            exposures: window.exposures.clone(),
+            window_id: window.window_id,
+            root_id: window.root_id,
+            atoms,
+            frame_timer: Mutex::new(None),
+            drop_target: DropTarget::new(atoms, window.window_id, size_xy),
+            drag_source: DragSource::new(
+                atoms,
+                window.window_id,
+                window.root_id,
+                window.selections.clone(),
+            ),
+            selections: window.selections.clone(),
+            keys: HeldKeys::default(),
+            capture: PointerCapture::new(window.window_id),
+            xembed: XembedClient::new(atoms),
+            popup: window
+                .popup
+                .then(|| PopupGrab::new(window.window_id, size_xy)),
+            #[cfg(feature = "ime")]
+            ime: window.ime.clone(),
+        }
+    }
+
+    /// Like XCB, `x11rb`'s API for getting window events is essentially identical to
+    /// `vst_window`'s event polling API.
+    ///
+    /// X11 events that have no `WindowEvent` equivalent are skipped, so that `None` is only
+    /// returned once the queue is actually empty. Exposures are passed on to the window, to be
+    /// redrawn by the next `present_pixels` call, and requests for the contents of the window's
+    /// selections are answered along the way. If an input method is in use, its composition
+    /// events are returned first, and key presses are forwarded to it.
+    fn poll_event(&self) -> Option<WindowEvent> {
+        self.drop_target.flush_status(self);
+        if let Some(popup) = &self.popup {
+            popup.ensure_grabbed(self);
+        }
+        if let Some(event) = self.drag_source.take_ended_event(self) {
+            return Some(event);
+        }
+        #[cfg(feature = "ime")]
+        if let Some(event) = self.ime.poll_event(&self.keys) {
+            return Some(event);
+        }
+        loop {
+            let event = self.connection.poll_for_event().ok()??;
+            if let Some(event) = self.convert_event(event) {
+                return Some(event);
+            }
+        }
+    }
+
+    /// The timer thread sends a `ClientMessage` to the window, which is converted into a `Frame`.
+    fn set_frame_interval(&self, interval: Option<Duration>) {
+        let mut frame_timer = self.frame_timer.lock().unwrap();
+        // The previous timer is stopped before the next one starts.
+        *frame_timer = None;
+        *frame_timer = interval.map(|interval| {
+            let sender = ClientMessageSender::new(self.window_id, self.atoms.frame);
+            FrameTimer::start(interval, move || sender.send())
+        });
+    }
+
+    /// Waits for the X server connection, or the input method's connection if there is one, to
+    /// become readable. Pending requests are flushed first, since they may generate the events
+    /// being waited for. Waiting also stops once a drop target that hasn't answered is due to be
+    /// given up on.
+    fn wait(&self, timeout: Option<Duration>) {
+        let _ = self.connection.flush();
+        let timeout = match (timeout, self.poll_timeout()) {
+            (Some(timeout), Some(drag_timeout)) => Some(timeout.min(drag_timeout)),
+            (timeout, drag_timeout) => timeout.or(drag_timeout),
+        };
+        let fd = self.connection.stream().as_raw_fd();
+        #[cfg(feature = "ime")]
+        if let Some(ime_fd) = self.ime.raw_fd() {
+            return wait_until_any_readable(&[fd, ime_fd], timeout);
+        }
+        wait_until_readable(fd, timeout);
+    }
+
+    /// Waking sends a `ClientMessage` to the window, which has no `WindowEvent` equivalent and is
+    /// skipped by `poll_event`.
+    fn wake_fn(&self) -> Option<WakeFn> {
+        let sender = ClientMessageSender::new(self.window_id, self.atoms.wake);
+        Some(Arc::new(move || sender.send()))
+    }
+
+    /// A drag's target is given up on by `poll_event` once it has failed to answer in time.
+    fn poll_timeout(&self) -> Option<Duration> {
+        self.drag_source.timeout()
+    }
+
+    fn set_file_drop_enabled(&self, enabled: bool) {
+        self.drop_target.set_enabled(self, enabled);
+    }
+
+    fn set_drop_accepted(&self, accepted: bool) {
+        self.drop_target.set_accepted(self, accepted);
+    }
+
+    fn start_drag(&self, data: DragData) -> bool {
+        self.drag_source.start(self, data)
+    }
+
+    fn set_capture_on_press(&self, capture: bool) {
+        self.capture.set_enabled(self, capture);
+    }
+
+    fn set_xembed_enabled(&self, enabled: bool) {
+        self.xembed.set_enabled(enabled);
+    }
+}
+
+impl Drop for EventSourceImpl {
+    /// Stops advertising XDND support, since nothing would answer drops onto the window anymore.
+    fn drop(&mut self) {
+        self.drop_target.set_enabled(self, false);
+    }
+}
+
+impl EventSourceImpl {
+    /// Translates an X11 event into a `WindowEvent`, if it has an equivalent.
+    fn convert_event(&self, event: Event) -> Option<WindowEvent> {
+        match event {
+            Event::Expose(expose) => {
+                self.exposures.add(Rect {
+                    x: expose.x as u32,
+                    y: expose.y as u32,
+                    width: expose.width as u32,
+                    height: expose.height as u32,
+                });
+                None
+            }
+            Event::MotionNotify(motion) => {
+                self.drag_source
+                    .handle_motion(self, motion.root_x, motion.root_y, motion.time);
+                Some(WindowEvent::CursorMovement(
+                    motion.event_x as f32 / self.size_xy.0 as f32,
+                    motion.event_y as f32 / self.size_xy.1 as f32,
+                ))
+            }
+            Event::KeyPress(key) => {
+                // Keys that the input method doesn't consume are sent back, and reported then.
+                #[cfg(feature = "ime")]
+                if self.ime.forward_key(&key) {
+                    return None;
+                }
+                Some(self.keys.press(key.detail))
+            }
+            Event::KeyRelease(key) => {
+                // Releases follow their presses through the input method.
+                #[cfg(feature = "ime")]
+                if self.ime.forward_key(&key) {
+                    return None;
+                }
+                self.keys.release(key.detail)
+            }
+            Event::FocusOut(_) => {
+                self.keys.clear();
+                None
+            }
+            Event::ButtonPress(button) => {
+                let dismissed = self
+                    .popup
+                    .as_ref()
+                    .and_then(|popup| popup.handle_press(self, button.event_x, button.event_y));
+                if dismissed.is_some() {
+                    return dismissed;
+                }
+                self.xembed.handle_press(self, button.time);
+                let button_detail = button.detail;
+                let event = convert_mouse_button_detail(button_detail).map(WindowEvent::MouseClick);
+                // Popups capture the pointer themselves while they are open.
+                if event.is_some() && self.popup.is_none() {
+                    self.capture.handle_press(self, button_detail);
+                }
+                event
+            }
+            Event::ButtonRelease(button) => {
+                self.drag_source.handle_release(self, button.time);
+                self.capture.handle_release(self, button.detail);
+                convert_mouse_button_detail(button.detail).map(WindowEvent::MouseRelease)
+            }
+            Event::ClientMessage(message) if message.type_ == self.atoms.frame => {
+                let frame_timer = self.frame_timer.lock().unwrap();
+                // Messages sent before the timer was stopped may still be queued.
+                frame_timer.as_ref().map(FrameTimer::frame_event)
+            }
+            Event::ClientMessage(message) if message.format == 32 => {
+                let data = message.data.as_data32();
+                let event = self
+                    .drag_source
+                    .handle_client_message(self, message.type_, data)
+                    .or_else(|| {
+                        self.drop_target
+                            .handle_client_message(self, message.type_, data)
+                    })
+                    .or_else(|| self.xembed.handle_client_message(message.type_, data));
+                // Forwarded key releases aren't sent to the window once it loses the focus.
+                if event == Some(WindowEvent::Focused(false)) {
+                    self.keys.clear();
+                }
+                event
+            }
+            Event::SelectionNotify(notify) if notify.selection == self.atoms.xdnd_selection => self
+                .drop_target
+                .handle_selection_notify(self, notify.property),
+            Event::SelectionRequest(request) => {
+                let request = SelectionRequest {
+                    requestor: request.requestor,
+                    selection: request.selection,
+                    target: request.target,
+                    property: request.property,
+                    time: request.time,
+                };
+                answer_request(self, &self.atoms, &self.selections, request);
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+fn convert_mouse_button_detail(detail: u8) -> Option<crate::event::MouseButton> {
+    use crate::event::MouseButton;
+    match detail {
+        1 => Some(MouseButton::Left),
+        2 => Some(MouseButton::Middle),
+        3 => Some(MouseButton::Right),
+        _ => None,
+    }
+}
+
+impl X11Connection for EventSourceImpl {
+    /// The other client's window may already have been destroyed, so errors are ignored.
+    fn send_client_message(&self, window: u32, type_: u32, data: [u32; 5]) {
+        let message = ClientMessageEvent::new(32, window, type_, data);
+        if let Ok(cookie) = self
+            .connection
+            .send_event(false, window, EventMask::NO_EVENT, message)
+        {
+            cookie.ignore_error();
+        }
+    }
+
+    /// The requestor's window may already have been destroyed, so errors are ignored.
+    fn set_property8(&self, window: u32, property: u32, type_: u32, data: &[u8]) {
+        if let Ok(cookie) =
+            self.connection
+                .change_property8(PropMode::REPLACE, window, property, type_, data)
+        {
+            cookie.ignore_error();
+        }
+    }
+
+    fn set_property32(&self, window: u32, property: u32, type_: u32, values: &[u32]) {
+        if let Ok(cookie) =
+            self.connection
+                .change_property32(PropMode::REPLACE, window, property, type_, values)
+        {
+            cookie.ignore_error();
+        }
+    }
+
+    /// The window may already have been destroyed when the event source is dropped.
+    fn delete_property(&self, window: u32, property: u32) {
+        if let Ok(cookie) = self.connection.delete_property(window, property) {
+            cookie.ignore_error();
+        }
+    }
+
+    fn grab_pointer(&self, window: u32, owner_events: bool) -> bool {
+        let event_mask =
+            EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION;
+        self.connection
+            .grab_pointer(
+                owner_events,
+                window,
+                event_mask,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+                x11rb::NONE,
+                x11rb::NONE,
+                x11rb::CURRENT_TIME,
+            )
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .is_some_and(|reply| reply.status == GrabStatus::SUCCESS)
+    }
+
+    fn ungrab_pointer(&self) {
+        let _ = self.connection.ungrab_pointer(x11rb::CURRENT_TIME);
+    }
+
+    fn flush(&self) {
+        let _ = self.connection.flush();
+    }
+}
+
+impl XdndConnection for EventSourceImpl {
+    fn get_property(&self, window: u32, property: u32, delete: bool) -> Option<Vec<u8>> {
+        let reply = self
+            .connection
+            .get_property(delete, window, property, AtomEnum::ANY, 0, u32::MAX / 4)
+            .ok()?
+            .reply()
+            .ok()?;
+        if reply.type_ == x11rb::NONE {
+            return None;
+        }
+        Some(reply.value)
+    }
+
+    fn convert_selection(
+        &self,
+        requestor: u32,
+        selection: u32,
+        target: u32,
+        property: u32,
+        time: u32,
+    ) {
+        let _ = self
+            .connection
+            .convert_selection(requestor, selection, target, property, time);
+    }
+
+    fn translate_from_root(&self, window: u32, x: i16, y: i16) -> Option<(i16, i16)> {
+        let reply = self
+            .connection
+            .translate_coordinates(self.root_id, window, x, y)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some((reply.dst_x, reply.dst_y))
+    }
+
+    fn child_at(&self, window: u32, x: i16, y: i16) -> Option<u32> {
+        let reply = self
+            .connection
+            .translate_coordinates(self.root_id, window, x, y)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some(reply.child).filter(|&child| child != x11rb::NONE)
+    }
+
+    fn intern_atom(&self, name: &str) -> Option<u32> {
+        let reply = self
+            .connection
+            .intern_atom(false, name.as_bytes())
+            .ok()?
+            .reply()
+            .ok()?;
+        Some(reply.atom)
+    }
+}
+
+/// The requestor's window may already have been destroyed, so errors are ignored.
+impl SelectionConnection for EventSourceImpl {
+    fn set_selection_owner(&self, owner: u32, selection: u32, time: u32) {
+        let _ = self.connection.set_selection_owner(owner, selection, time);
+    }
+
+    fn send_selection_notify(
+        &self,
+        requestor: u32,
+        selection: u32,
+        target: u32,
+        property: u32,
+        time: u32,
+    ) {
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time,
+            requestor,
+            selection,
+            target,
+            property,
+        };
+        if let Ok(cookie) =
+            self.connection
+                .send_event(false, requestor, EventMask::NO_EVENT, notify)
+        {
+            cookie.ignore_error();
+        }
+    }
+
+    fn maximum_request_bytes(&self) -> usize {
+        self.connection.maximum_request_bytes()
+    }
+}
+
+impl AsRawFd for EventSourceImpl {
+    fn as_raw_fd(&self) -> RawFd {
+        self.connection.stream().as_raw_fd()
+    }
+}
+
+/// Sends empty `ClientMessage`s of a given type to a window from other threads. With an empty
+/// event mask, the X server delivers them to the client that created the window.
+///
+/// A separate connection is used, since `RustConnection` may read incoming events from the socket
+/// while sending requests, and events read by another thread wouldn't make the shared connection's
+/// file descriptor readable. It is opened up front on the thread creating the sender, so that no
+/// connection handshake happens on the sending threads, such as the audio thread.
+struct ClientMessageSender {
+    window_id: u32,
+    type_: u32,
+    /// `None` if the connection couldn't be opened, in which case nothing is sent.
+    connection: Option<RustConnection>,
+}
+
+impl ClientMessageSender {
+    fn new(window_id: u32, type_: u32) -> Self {
+        Self {
+            window_id,
+            type_,
+            connection: x11rb::connect(None).ok().map(|(c, _)| c),
+        }
+    }
+
+    fn send(&self) {
+        let connection = match &self.connection {
+            Some(connection) => connection,
+            None => return,
+        };
+        let message = ClientMessageEvent::new(32, self.window_id, self.type_, [0u32; 5]);
+        // The window may already have been destroyed, in which case the `BadWindow` error is
+        // ignored.
+        if let Ok(cookie) =
+            connection.send_event(false, self.window_id, EventMask::NO_EVENT, message)
+        {
+            cookie.ignore_error();
+        }
+        let _ = connection.flush();
+    }
+}
@@ -0,0 +1,4 @@
+//! Platform-specific utilities for Unix, using the pure-Rust `x11rb` X11 client library.
+
+pub mod event_source;
+pub mod window;
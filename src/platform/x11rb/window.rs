@@ -0,0 +1,632 @@
+//! Provides window setup logic specific to the Unix platform, using `x11rb`.
+
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::shm::ConnectionExt as _;
+use x11rb::protocol::xkb::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{
+    AtomEnum, ConfigureWindowAux, ConnectionExt as _, CreateGCAux, CreateWindowAux, EventMask,
+    GrabMode, GrabStatus, ImageFormat, ImageOrder, PropMode, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_FROM_PARENT, CURRENT_TIME, NONE};
+
+use crate::platform::x11::atoms::Atoms;
+use crate::platform::x11::image::{clip_damage, decode_image, Exposures, ImageTarget, Presenter};
+#[cfg(feature = "ime")]
+use crate::platform::x11::ime::InputMethod;
+use crate::platform::x11::selection::{read_text, text_targets, SelectionReader, Selections};
+use crate::platform::x11::wait::wait_until_readable;
+use crate::platform::x11::xembed::XEMBED_INFO;
+use crate::platform::{EditorWindowBackend, Image, PresentMethod, Rect};
+
+/// "User-specified size" flag for WM_NORMAL_HINTS
+const USSIZE: u32 = 2;
+/// "Program-specified" min size flag for WM_NORMAL_HINTS
+const PMINSIZE: u32 = 16;
+/// "Program-specified" max size flag for WM_NORMAL_HINTS
+const PMAXSIZE: u32 = 32;
+
+pub(in crate::platform) struct EditorWindowImpl {
+    /// Shared with the corresponding `EventSourceImpl`. The connection to the X server is closed
+    /// once both have been dropped, in either order.
+    pub connection: Arc<RustConnection>,
+    pub window_id: u32,
+    pub root_id: u32,
+    /// Whether this is a popup created with `create_popup`, rather than the editor's window.
+    pub popup: bool,
+    gc_id: u32,
+    depth: u8,
+    /// Whether the X server expects image data in big-endian byte order.
+    image_msb_first: bool,
+    size_xy: (i32, i32),
+    presenter: Mutex<Presenter>,
+    /// Filled in by the corresponding `EventSourceImpl` as `Expose` events arrive, and drained by
+    /// the next `present_pixels` call.
+    pub exposures: Exposures,
+    pub atoms: Atoms,
+    /// Data offered through the selections owned by the window, handed out by the corresponding
+    /// `EventSourceImpl` as other clients request it.
+    pub selections: Selections,
+    /// Opened the first time another client's selection is read.
+    selection_reader: Mutex<Option<SelectionReaderImpl>>,
+    /// Fed key presses by the corresponding `EventSourceImpl`.
+    #[cfg(feature = "ime")]
+    pub ime: InputMethod,
+}
+
+impl Drop for EditorWindowImpl {
+    /// The X server only cleans up a client's resources once it disconnects. Since the connection
+    /// may outlive the window (if the `EventSource` is still around), the window and its graphics
+    /// context are explicitly destroyed here.
+    ///
+    /// Hosts often destroy the parent window (and with it, this window) before closing the editor.
+    /// The resulting `BadWindow` error is ignored rather than being delivered to the
+    /// `EventSource`. Connection errors are ignored as well, since the connection may already be
+    /// broken.
+    fn drop(&mut self) {
+        let mut presenter =
+            std::mem::replace(self.presenter.get_mut().unwrap(), Presenter::Uninitialized);
+        presenter.release(self);
+        if let Ok(cookie) = self.connection.destroy_window(self.window_id) {
+            cookie.ignore_error();
+        }
+        if let Ok(cookie) = self.connection.free_gc(self.gc_id) {
+            cookie.ignore_error();
+        }
+        let _ = self.connection.flush();
+    }
+}
+
+/// Where a window is created.
+enum Placement {
+    /// Inside the host's window, at its top-left.
+    Embedded { parent: u32 },
+    /// Above all other windows, at the given root window coordinates.
+    Popup { x: i16, y: i16 },
+}
+
+impl EditorWindowImpl {
+    /// Creates and maps a window, with its own connection to the X server.
+    fn create(placement: Placement, size_xy: (i32, i32)) -> Self {
+        let (connection, screen_num) = x11rb::connect(None).expect("Connect to X server");
+        let screen = &connection.setup().roots[screen_num];
+
+        let foreground = connection.generate_id().unwrap();
+        let values = CreateGCAux::new()
+            .foreground(screen.black_pixel)
+            .graphics_exposures(0);
+        connection
+            .create_gc(foreground, screen.root, &values)
+            .unwrap();
+
+        let event_mask = EventMask::EXPOSURE
+            | EventMask::KEY_PRESS
+            | EventMask::KEY_RELEASE
+            | EventMask::FOCUS_CHANGE
+            | EventMask::BUTTON_PRESS
+            | EventMask::BUTTON_RELEASE
+            | EventMask::POINTER_MOTION;
+        let wid = connection.generate_id().unwrap();
+        let (parent, x, y, window_type) = match placement {
+            Placement::Embedded { parent } => (parent, 0, 0, "_NET_WM_WINDOW_TYPE_DIALOG"),
+            Placement::Popup { x, y } => (screen.root, x, y, "_NET_WM_WINDOW_TYPE_POPUP_MENU"),
+        };
+        let popup = matches!(placement, Placement::Popup { .. });
+        let values = CreateWindowAux::new()
+            .background_pixel(screen.black_pixel)
+            .override_redirect(u32::from(popup))
+            .event_mask(event_mask);
+
+        connection
+            .create_window(
+                COPY_FROM_PARENT as u8,
+                wid,
+                parent,
+                x,
+                y,
+                size_xy.0 as u16,
+                size_xy.1 as u16,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                screen.root_visual,
+                &values,
+            )
+            .unwrap();
+
+        let net_wm_window_type = x11rb_intern_string(&connection, "_NET_WM_WINDOW_TYPE");
+        let net_wm_window_type_value = x11rb_intern_string(&connection, window_type);
+        connection
+            .change_property32(
+                PropMode::REPLACE,
+                wid,
+                net_wm_window_type,
+                AtomEnum::ATOM,
+                &[net_wm_window_type_value],
+            )
+            .unwrap();
+
+        let wm_normal_hints = x11rb_intern_string(&connection, "WM_NORMAL_HINTS");
+        let size_hints = {
+            let size_x = size_xy.0 as u32;
+            let size_y = size_xy.1 as u32;
+            let flags = USSIZE | PMINSIZE | PMAXSIZE;
+            [
+                flags, 0, 0, 0, 0, size_x, size_y, size_x, size_y, 0, 0, 0, 0, size_x, size_y,
+            ]
+        };
+        connection
+            .change_property32(
+                PropMode::REPLACE,
+                wid,
+                wm_normal_hints,
+                AtomEnum::WM_SIZE_HINTS,
+                &size_hints,
+            )
+            .unwrap();
+
+        let atoms = Atoms::from_interned(&x11rb_intern_atoms(&connection, Atoms::NAMES));
+        x11rb_enable_detectable_auto_repeat(&connection);
+
+        if !popup {
+            connection
+                .change_property32(
+                    PropMode::REPLACE,
+                    wid,
+                    atoms.xembed_info,
+                    atoms.xembed_info,
+                    &XEMBED_INFO,
+                )
+                .unwrap();
+        }
+
+        connection.map_window(wid).unwrap();
+        connection.flush().unwrap();
+
+        let depth = screen.root_depth;
+        let image_msb_first = connection.setup().image_byte_order == ImageOrder::MSB_FIRST;
+
+        let root_id = screen.root;
+
+        Self {
+            connection: Arc::new(connection),
+            window_id: wid,
+            root_id,
+            popup,
+            gc_id: foreground,
+            depth,
+            image_msb_first,
+            size_xy,
+            presenter: Mutex::new(Presenter::Uninitialized),
+            exposures: Exposures::default(),
+            atoms,
+            selections: Selections::default(),
+            selection_reader: Mutex::new(None),
+            // Popups never have the focus, so connecting them to the server would be wasted.
+            #[cfg(feature = "ime")]
+            ime: if popup {
+                InputMethod::disconnected()
+            } else {
+                InputMethod::connect(wid)
+            },
+        }
+    }
+}
+
+impl EditorWindowBackend for EditorWindowImpl {
+    /// The VST API provides an X11 window id on Unix, which `x11rb` can use as the parent of a new
+    /// child window, exactly as with the `xcb` backend.
+    ///
+    /// The connection is reference-counted so that the `EventSource` can share it without any
+    /// unsafe ownership juggling.
+    fn build(parent: *mut std::os::raw::c_void, size_xy: (i32, i32)) -> Self {
+        Self::create(
+            Placement::Embedded {
+                parent: parent as u32,
+            },
+            size_xy,
+        )
+    }
+
+    /// Pixels are uploaded using MIT-SHM if possible, or `put_image` otherwise, drawn with the
+    /// graphics context created in `build`. Areas exposed since the last frame are redrawn along
+    /// with the dirty ones.
+    fn present_pixels(&self, pixels: &[u32], width: u32, height: u32, dirty: &[Rect]) {
+        let mut damage = self.exposures.take();
+        damage.extend_from_slice(dirty);
+        let damage = clip_damage(&damage, width, height);
+        self.presenter
+            .lock()
+            .unwrap()
+            .present(self, pixels, width, height, &damage);
+    }
+
+    fn present_method(&self) -> PresentMethod {
+        let size_xy = (self.size_xy.0 as u32, self.size_xy.1 as u32);
+        self.presenter.lock().unwrap().method(self, size_xy)
+    }
+
+    /// The window's contents are read back with `get_image`, which fails if the window isn't
+    /// viewable.
+    fn capture(&self) -> Option<Image> {
+        let (width, height) = (self.size_xy.0 as u32, self.size_xy.1 as u32);
+        let reply = self
+            .connection
+            .get_image(
+                ImageFormat::Z_PIXMAP,
+                self.window_id,
+                0,
+                0,
+                width as u16,
+                height as u16,
+                !0,
+            )
+            .ok()?
+            .reply()
+            .ok()?;
+        decode_image(&reply.data, width, height, self.image_msb_first)
+    }
+
+    /// If the window owns the clipboard, its own text is returned directly, since the event source
+    /// can't answer requests while this thread waits. Otherwise, the owner is asked for the text
+    /// over a separate connection, so that no events meant for the event source are consumed.
+    fn clipboard_text(&self) -> Option<String> {
+        let owner = self
+            .connection
+            .get_selection_owner(self.atoms.clipboard)
+            .ok()?
+            .reply()
+            .ok()?
+            .owner;
+        if owner == self.window_id {
+            let text = self
+                .selections
+                .get(self.atoms.clipboard, self.atoms.utf8_string)?;
+            return String::from_utf8(text).ok();
+        }
+        if owner == NONE {
+            return None;
+        }
+        let mut reader = self.selection_reader.lock().unwrap();
+        if reader.is_none() {
+            *reader = SelectionReaderImpl::connect();
+        }
+        read_text(reader.as_ref()?, &self.atoms, self.atoms.clipboard)
+    }
+
+    /// The window takes ownership of the clipboard, and the event source answers other clients'
+    /// requests for its contents.
+    fn set_clipboard_text(&self, text: &str) {
+        self.selections
+            .offer(self.atoms.clipboard, text_targets(&self.atoms, text));
+        let _ =
+            self.connection
+                .set_selection_owner(self.window_id, self.atoms.clipboard, CURRENT_TIME);
+        let _ = self.connection.flush();
+    }
+
+    /// The popup's position is translated from the window's coordinates to the root window's.
+    fn create_popup(&self, position: (i32, i32), size_xy: (i32, i32)) -> Option<Self> {
+        let position = self
+            .connection
+            .translate_coordinates(
+                self.window_id,
+                self.root_id,
+                clamp_coordinate(position.0),
+                clamp_coordinate(position.1),
+            )
+            .ok()?
+            .reply()
+            .ok()?;
+        let (x, y) = (position.dst_x, position.dst_y);
+        Some(Self::create(Placement::Popup { x, y }, size_xy))
+    }
+
+    fn cursor_position(&self) -> Option<(i32, i32)> {
+        let reply = self
+            .connection
+            .query_pointer(self.window_id)
+            .ok()?
+            .reply()
+            .ok()?;
+        if !reply.same_screen {
+            return None;
+        }
+        Some((reply.win_x.into(), reply.win_y.into()))
+    }
+
+    fn position(&self) -> Option<(i32, i32)> {
+        let reply = self
+            .connection
+            .get_geometry(self.window_id)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some((reply.x.into(), reply.y.into()))
+    }
+
+    fn set_position(&self, x: i32, y: i32) {
+        let (x, y) = (clamp_coordinate(x), clamp_coordinate(y));
+        let aux = ConfigureWindowAux::new().x(i32::from(x)).y(i32::from(y));
+        let _ = self.connection.configure_window(self.window_id, &aux);
+        let _ = self.connection.flush();
+    }
+
+    fn screen_position(&self) -> Option<(i32, i32)> {
+        let reply = self
+            .connection
+            .translate_coordinates(self.window_id, self.root_id, 0, 0)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some((reply.dst_x.into(), reply.dst_y.into()))
+    }
+
+    /// The root window's geometry is queried, since the screen may have been resized since the
+    /// connection was opened.
+    fn screen_size(&self) -> Option<(i32, i32)> {
+        let reply = self
+            .connection
+            .get_geometry(self.root_id)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some((reply.width.into(), reply.height.into()))
+    }
+
+    fn x11_window_id(&self) -> Option<u32> {
+        Some(self.window_id)
+    }
+
+    /// The pointer is grabbed with owner events, on the connection shared with the event source.
+    fn grab_pointer(&self) -> bool {
+        let event_mask =
+            EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION;
+        self.connection
+            .grab_pointer(
+                true,
+                self.window_id,
+                event_mask,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+                NONE,
+                NONE,
+                CURRENT_TIME,
+            )
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .is_some_and(|reply| reply.status == GrabStatus::SUCCESS)
+    }
+
+    fn ungrab_pointer(&self) {
+        let _ = self.connection.ungrab_pointer(CURRENT_TIME);
+        let _ = self.connection.flush();
+    }
+
+    #[cfg(feature = "ime")]
+    fn set_ime_position(&self, x: i32, y: i32) {
+        self.ime.set_spot(clamp_coordinate(x), clamp_coordinate(y));
+    }
+}
+
+impl ImageTarget for EditorWindowImpl {
+    fn maximum_request_bytes(&self) -> usize {
+        self.connection.maximum_request_bytes()
+    }
+
+    fn image_msb_first(&self) -> bool {
+        self.image_msb_first
+    }
+
+    fn put_image(&self, rect: &Rect, data: &[u8]) {
+        let _ = self.connection.put_image(
+            ImageFormat::Z_PIXMAP,
+            self.window_id,
+            self.gc_id,
+            rect.width as u16,
+            rect.height as u16,
+            rect.x as i16,
+            rect.y as i16,
+            0,
+            self.depth,
+            data,
+        );
+    }
+
+    fn shm_attach(&self, shmid: u32) -> Option<u32> {
+        self.connection.shm_query_version().ok()?.reply().ok()?;
+        let shmseg = self.connection.generate_id().ok()?;
+        self.connection
+            .shm_attach(shmseg, shmid, false)
+            .ok()?
+            .check()
+            .ok()?;
+        Some(shmseg)
+    }
+
+    fn shm_detach(&self, shmseg: u32) {
+        let _ = self.connection.shm_detach(shmseg);
+    }
+
+    fn shm_put_image(&self, shmseg: u32, total_width: u32, total_height: u32, rect: &Rect) {
+        let _ = self.connection.shm_put_image(
+            self.window_id,
+            self.gc_id,
+            total_width as u16,
+            total_height as u16,
+            rect.x as u16,
+            rect.y as u16,
+            rect.width as u16,
+            rect.height as u16,
+            rect.x as i16,
+            rect.y as i16,
+            self.depth,
+            ImageFormat::Z_PIXMAP.into(),
+            false,
+            shmseg,
+            0,
+        );
+    }
+
+    fn sync(&self) {
+        let _ = self
+            .connection
+            .get_input_focus()
+            .map(|cookie| cookie.reply());
+    }
+
+    fn flush(&self) {
+        let _ = self.connection.flush();
+    }
+}
+
+/// Clamps a coordinate to the range of X11 coordinates.
+fn clamp_coordinate(value: i32) -> i16 {
+    value.clamp(i16::MIN.into(), i16::MAX.into()) as i16
+}
+
+/// Like XCB, `x11rb` identifies some window properties using `Atom`s, which are identifiers for
+/// strings that have been previously interned.
+fn x11rb_intern_string(connection: &RustConnection, value: &str) -> u32 {
+    match connection
+        .intern_atom(false, value.as_bytes())
+        .map(|cookie| cookie.reply())
+    {
+        Ok(Ok(reply)) => reply.atom,
+        _ => panic!("could not intern {} atom", value),
+    }
+}
+
+/// Interns several strings at once, waiting for a single round trip to the X server.
+fn x11rb_intern_atoms(connection: &RustConnection, values: &[&str]) -> Vec<u32> {
+    let cookies: Vec<_> = values
+        .iter()
+        .map(|value| connection.intern_atom(false, value.as_bytes()))
+        .collect();
+    cookies
+        .into_iter()
+        .zip(values)
+        .map(
+            |(cookie, value)| match cookie.map(|cookie| cookie.reply()) {
+                Ok(Ok(reply)) => reply.atom,
+                _ => panic!("could not intern {} atom", value),
+            },
+        )
+        .collect()
+}
+
+/// Asks the X server to report held keys as repeated presses followed by a single release, rather
+/// than as pairs of releases and presses. Servers without the XKB extension keep sending pairs.
+fn x11rb_enable_detectable_auto_repeat(connection: &RustConnection) {
+    let supported = connection
+        .xkb_use_extension(1, 0)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .is_some_and(|reply| reply.supported);
+    if !supported {
+        return;
+    }
+    let flag = xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT;
+    let _ = connection
+        .xkb_per_client_flags(
+            xkb::ID::USE_CORE_KBD.into(),
+            flag,
+            flag,
+            xkb::BoolCtrl::from(0u32),
+            xkb::BoolCtrl::from(0u32),
+            xkb::BoolCtrl::from(0u32),
+        )
+        .map(|cookie| cookie.reply());
+}
+
+/// Reads selections owned by other clients, using its own connection and an invisible window to
+/// receive the converted data.
+struct SelectionReaderImpl {
+    connection: RustConnection,
+    window_id: u32,
+}
+
+impl SelectionReaderImpl {
+    fn connect() -> Option<Self> {
+        let (connection, screen_num) = x11rb::connect(None).ok()?;
+        let root = connection.setup().roots.get(screen_num)?.root;
+        let window_id = connection.generate_id().ok()?;
+        connection
+            .create_window(
+                COPY_FROM_PARENT as u8,
+                window_id,
+                root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_ONLY,
+                COPY_FROM_PARENT,
+                &CreateWindowAux::new(),
+            )
+            .ok()?;
+        Some(Self {
+            connection,
+            window_id,
+        })
+    }
+}
+
+impl SelectionReader for SelectionReaderImpl {
+    fn convert_selection(
+        &self,
+        selection: u32,
+        target: u32,
+        property: u32,
+        deadline: Instant,
+    ) -> bool {
+        let requested = self
+            .connection
+            .convert_selection(self.window_id, selection, target, property, CURRENT_TIME)
+            .is_ok();
+        if !requested || self.connection.flush().is_err() {
+            return false;
+        }
+        loop {
+            while let Ok(Some(event)) = self.connection.poll_for_event() {
+                // Answers to earlier conversions that timed out may still arrive.
+                if let Event::SelectionNotify(notify) = event {
+                    if notify.selection == selection && notify.target == target {
+                        return notify.property != NONE;
+                    }
+                }
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            wait_until_readable(self.connection.stream().as_raw_fd(), Some(remaining));
+        }
+    }
+
+    fn take_property(&self, property: u32) -> Option<(u32, Vec<u8>)> {
+        let reply = self
+            .connection
+            .get_property(
+                true,
+                self.window_id,
+                property,
+                AtomEnum::ANY,
+                0,
+                u32::MAX / 4,
+            )
+            .ok()?
+            .reply()
+            .ok()?;
+        if reply.type_ == NONE {
+            return None;
+        }
+        Some((reply.type_, reply.value))
+    }
+}
@@ -0,0 +1,151 @@
+//! Recording of `WindowEvent` streams, and deterministic replay of those recordings.
+//!
+//! Recordings are stored as JSON lines. Each line holds a single `WindowEvent`, along with the
+//! time at which it was received, relative to the start of the recording.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::WindowEvent;
+use crate::platform::EventSource;
+
+/// A single entry in a recording.
+#[derive(Serialize, Deserialize)]
+struct RecordedEvent {
+    time: Duration,
+    event: WindowEvent,
+}
+
+/// Wraps an `EventSource`, writing every event it produces to a recording.
+///
+/// The recording can later be played back with a `ReplayEventSource`.
+pub struct EventRecorder<W: Write> {
+    source: EventSource,
+    output: RefCell<W>,
+    start: Instant,
+    error: RefCell<Option<io::Error>>,
+}
+
+impl EventRecorder<BufWriter<File>> {
+    /// Starts recording the events from `source` to a newly created file at `path`.
+    pub fn create<P: AsRef<Path>>(source: EventSource, path: P) -> io::Result<Self> {
+        Ok(Self::new(source, BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> EventRecorder<W> {
+    /// Starts recording the events from `source` to `output`.
+    pub fn new(source: EventSource, output: W) -> Self {
+        Self {
+            source,
+            output: RefCell::new(output),
+            start: Instant::now(),
+            error: RefCell::new(None),
+        }
+    }
+
+    /// Returns the next `WindowEvent` from the wrapped `EventSource`, if one is available, and
+    /// records it. This should be called in a `while let` loop until empty.
+    ///
+    /// Write errors do not interrupt the flow of events; the first one is reported by `finish`.
+    pub fn poll_event(&self) -> Option<WindowEvent> {
+        let event = self.source.poll_event()?;
+        let mut error = self.error.borrow_mut();
+        if error.is_none() {
+            if let Err(e) = self.record(event.clone()) {
+                *error = Some(e);
+            }
+        }
+        Some(event)
+    }
+
+    fn record(&self, event: WindowEvent) -> io::Result<()> {
+        let entry = RecordedEvent {
+            time: self.start.elapsed(),
+            event,
+        };
+        let mut output = self.output.borrow_mut();
+        serde_json::to_writer(&mut *output, &entry)?;
+        output.write_all(b"\n")
+    }
+
+    /// Stops recording and flushes the recording, returning the original `EventSource` and
+    /// recording output.
+    pub fn finish(self) -> io::Result<(EventSource, W)> {
+        if let Some(e) = self.error.into_inner() {
+            return Err(e);
+        }
+        let mut output = self.output.into_inner();
+        output.flush()?;
+        Ok((self.source, output))
+    }
+}
+
+/// Plays back a recording made with an `EventRecorder`.
+///
+/// By default, events become available at the same pace as they were originally received,
+/// measured from the first call to `poll_event`.
+pub struct ReplayEventSource {
+    events: RefCell<VecDeque<RecordedEvent>>,
+    start: Cell<Option<Instant>>,
+    paced: bool,
+}
+
+impl ReplayEventSource {
+    /// Loads a recording from the file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Loads a recording from `reader`.
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut events = VecDeque::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            events.push_back(serde_json::from_str(&line)?);
+        }
+        Ok(Self {
+            events: RefCell::new(events),
+            start: Cell::new(None),
+            paced: true,
+        })
+    }
+
+    /// Makes every remaining event available immediately, rather than at the original pace.
+    pub fn without_pacing(mut self) -> Self {
+        self.paced = false;
+        self
+    }
+
+    /// Returns the next recorded `WindowEvent`, if it is due. This should be called in a
+    /// `while let` loop until empty.
+    pub fn poll_event(&self) -> Option<WindowEvent> {
+        let start = match self.start.get() {
+            Some(start) => start,
+            None => {
+                let now = Instant::now();
+                self.start.set(Some(now));
+                now
+            }
+        };
+        let mut events = self.events.borrow_mut();
+        if self.paced && events.front()?.time > start.elapsed() {
+            return None;
+        }
+        events.pop_front().map(|entry| entry.event)
+    }
+
+    /// Returns `true` once every recorded event has been returned by `poll_event`.
+    pub fn is_finished(&self) -> bool {
+        self.events.borrow().is_empty()
+    }
+}
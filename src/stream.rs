@@ -0,0 +1,80 @@
+//! An asynchronous `Stream` of `WindowEvent`s, for async-based UI frameworks.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_io::{Async, Timer};
+use futures_core::Stream;
+
+use crate::event::WindowEvent;
+use crate::platform::EventSource;
+
+/// A `Stream` of `WindowEvent`s, created by `EventSource::into_stream`.
+///
+/// The stream is driven by the `async-io` reactor, which wakes it up whenever the connection to
+/// the X server becomes readable, or once `EventSource::poll_timeout` elapses. It only ends if the
+/// reactor fails.
+pub struct EventStream {
+    source: Async<EventSource>,
+    /// Wakes the stream up when the event source has to be polled without becoming readable.
+    timer: Timer,
+}
+
+impl EventSource {
+    /// Converts the event source into a `Stream` of `WindowEvent`s, registered with the `async-io`
+    /// reactor. This allows async-based UIs to receive events with `stream.next().await` rather
+    /// than polling from `Editor::idle`.
+    ///
+    /// The same caveats apply as when registering the event source with an external event loop
+    /// through `AsRawFd`: if the `EditorWindow` is used from another thread, some events may only
+    /// be noticed once the next event arrives.
+    ///
+    /// Returns an error if the X server connection can't be registered with the reactor.
+    pub fn into_stream(self) -> io::Result<EventStream> {
+        Ok(EventStream {
+            source: Async::new(self)?,
+            timer: Timer::never(),
+        })
+    }
+}
+
+impl EventStream {
+    /// Returns the underlying `EventSource`, e.g. to create a `Waker`.
+    pub fn get_ref(&self) -> &EventSource {
+        self.source.get_ref()
+    }
+
+    /// Deregisters the event source from the reactor and returns it.
+    pub fn into_inner(self) -> io::Result<EventSource> {
+        self.source.into_inner()
+    }
+}
+
+impl Stream for EventStream {
+    type Item = WindowEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<WindowEvent>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(event) = this.source.get_ref().poll_event() {
+                return Poll::Ready(Some(event));
+            }
+            // The event queue is drained before waiting, since events may already have been read
+            // from the connection by the X11 client library.
+            match this.source.poll_readable(cx) {
+                Poll::Ready(Ok(())) => continue,
+                Poll::Ready(Err(_)) => return Poll::Ready(None),
+                Poll::Pending => {}
+            }
+            match this.source.get_ref().poll_timeout() {
+                Some(timeout) => this.timer.set_after(timeout),
+                None => return Poll::Pending,
+            }
+            if Pin::new(&mut this.timer).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
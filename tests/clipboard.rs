@@ -0,0 +1,234 @@
+//! Checks that text can be exchanged with other clients through the clipboard.
+
+#![cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+
+mod common;
+
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use common::{open_editor, wait_until, Editor, XServer};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ConnectionExt as _, CreateWindowAux, EventMask, PropMode, SelectionNotifyEvent,
+    WindowClass, SELECTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::Event;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_FROM_PARENT, CURRENT_TIME, NONE};
+
+const SIZE_XY: (i32, i32) = (300, 200);
+
+#[test]
+fn text_set_by_editor_can_be_pasted_elsewhere() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let text = "Gain: -3.5 dB ✓";
+    window.clipboard().set_text(text);
+    let clipboard = server.atom("CLIPBOARD");
+    assert!(wait_until(|| {
+        let reply = server.connection.get_selection_owner(clipboard).unwrap();
+        reply.reply().unwrap().owner == child
+    }));
+    assert_eq!(window.clipboard().get_text().as_deref(), Some(text));
+
+    let requestor = server.create_hidden_window();
+    let property = server.atom("PASTED");
+    let paste = |target: &str| -> Option<(u32, Vec<u8>)> {
+        let target = server.atom(target);
+        server
+            .connection
+            .convert_selection(requestor, clipboard, target, property, CURRENT_TIME)
+            .unwrap();
+        server.sync();
+        let mut notified = None;
+        // The editor answers requests while its event source is polled.
+        assert!(wait_until(|| {
+            while event_source.poll_event().is_some() {}
+            while let Some(event) = server.connection.poll_for_event().unwrap() {
+                if let Event::SelectionNotify(notify) = event {
+                    notified = Some(notify.property);
+                }
+            }
+            notified.is_some()
+        }));
+        if notified == Some(NONE) {
+            return None;
+        }
+        let reply = server
+            .connection
+            .get_property(true, requestor, property, AtomEnum::ANY, 0, 1 << 16)
+            .unwrap()
+            .reply()
+            .unwrap();
+        Some((reply.type_, reply.value))
+    };
+
+    assert_eq!(
+        paste("UTF8_STRING"),
+        Some((server.atom("UTF8_STRING"), text.as_bytes().to_vec()))
+    );
+    let (type_, targets) = paste("TARGETS").unwrap();
+    assert_eq!(type_, u32::from(AtomEnum::ATOM));
+    let targets: Vec<u32> = targets
+        .chunks_exact(4)
+        .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    assert!(targets.contains(&server.atom("UTF8_STRING")));
+    // The text can't be encoded as Latin-1.
+    assert!(!targets.contains(&u32::from(AtomEnum::STRING)));
+    assert_eq!(paste("STRING"), None);
+
+    // Text too large for a single request is refused, rather than breaking the connection.
+    window.clipboard().set_text(&"a".repeat(17 << 20));
+    assert_eq!(paste("UTF8_STRING"), None);
+    window.clipboard().set_text(text);
+    assert_eq!(
+        paste("UTF8_STRING"),
+        Some((server.atom("UTF8_STRING"), text.as_bytes().to_vec()))
+    );
+}
+
+#[test]
+fn text_owned_by_other_client_can_be_read() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let owner = spawn_clipboard_owner(|target| match target {
+        "UTF8_STRING" => Some("Preset: Wärme".as_bytes().to_vec()),
+        _ => None,
+    });
+    assert_eq!(
+        window.clipboard().get_text().as_deref(),
+        Some("Preset: Wärme")
+    );
+    owner.join().unwrap();
+}
+
+#[test]
+fn latin1_text_is_read_if_utf8_is_refused() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let owner = spawn_clipboard_owner(|target| match target {
+        "STRING" => Some(b"Caf\xe9".to_vec()),
+        _ => None,
+    });
+    assert_eq!(window.clipboard().get_text().as_deref(), Some("Café"));
+    owner.join().unwrap();
+}
+
+/// Takes ownership of the clipboard on a separate connection, and answers requests for it from a
+/// background thread until one succeeds or a few seconds have passed. `convert` returns the
+/// contents of the clipboard for a given target name, or `None` to refuse the request.
+///
+/// Returns once the clipboard is owned.
+fn spawn_clipboard_owner(convert: fn(&str) -> Option<Vec<u8>>) -> JoinHandle<()> {
+    let (owned_sender, owned) = mpsc::channel();
+    let thread = std::thread::spawn(move || {
+        let (connection, screen_num) = x11rb::connect(None).unwrap();
+        let screen = &connection.setup().roots[screen_num];
+        let window = connection.generate_id().unwrap();
+        connection
+            .create_window(
+                COPY_FROM_PARENT as u8,
+                window,
+                screen.root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_ONLY,
+                screen.root_visual,
+                &CreateWindowAux::new(),
+            )
+            .unwrap();
+        let clipboard = connection
+            .intern_atom(false, b"CLIPBOARD")
+            .unwrap()
+            .reply()
+            .unwrap()
+            .atom;
+        connection
+            .set_selection_owner(window, clipboard, CURRENT_TIME)
+            .unwrap();
+        connection.get_input_focus().unwrap().reply().unwrap();
+        owned_sender.send(()).unwrap();
+
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            let request = match connection.poll_for_event().unwrap() {
+                Some(Event::SelectionRequest(request)) => request,
+                _ => {
+                    std::thread::sleep(Duration::from_millis(10));
+                    continue;
+                }
+            };
+            let target = connection.get_atom_name(request.target).unwrap();
+            let target = String::from_utf8(target.reply().unwrap().name).unwrap();
+            let data = convert(&target);
+            if let Some(data) = &data {
+                connection
+                    .change_property8(
+                        PropMode::REPLACE,
+                        request.requestor,
+                        request.property,
+                        request.target,
+                        data,
+                    )
+                    .unwrap();
+            }
+            let notify = SelectionNotifyEvent {
+                response_type: SELECTION_NOTIFY_EVENT,
+                sequence: 0,
+                time: request.time,
+                requestor: request.requestor,
+                selection: request.selection,
+                target: request.target,
+                property: if data.is_some() {
+                    request.property
+                } else {
+                    NONE
+                },
+            };
+            connection
+                .send_event(false, request.requestor, EventMask::NO_EVENT, notify)
+                .unwrap();
+            connection.flush().unwrap();
+            if data.is_some() {
+                return;
+            }
+        }
+        panic!("the clipboard was never read");
+    });
+    owned.recv().unwrap();
+    thread
+}
@@ -0,0 +1,453 @@
+//! Shared harness for integration tests that run against a real X server.
+//!
+//! If `DISPLAY` is set when the tests start, that server is used. Otherwise, a fresh `Xvfb`
+//! server is started for each test. If neither is available, tests fail, unless
+//! `VST_WINDOW_SKIP_X_TESTS` is set, in which case they are skipped.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+use x11rb::connection::Connection;
+use x11rb::protocol::res::ConnectionExt as _;
+use x11rb::protocol::xproto::{
+    ConnectionExt as _, CreateWindowAux, EventMask, GrabMode, GrabStatus, ImageFormat, ImageOrder,
+    MapState, Window, WindowClass, BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, KEY_PRESS_EVENT,
+    KEY_RELEASE_EVENT, MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+use x11rb::COPY_FROM_PARENT;
+
+/// `setup` connects to whichever server `DISPLAY` points to, so only one test may use the process
+/// environment at a time.
+static SERVER_LOCK: Mutex<()> = Mutex::new(());
+
+/// The value of `DISPLAY` before any test modified it.
+static ORIGINAL_DISPLAY: OnceLock<Option<OsString>> = OnceLock::new();
+
+/// Environment variable that makes tests pass without running when no X server is available.
+const SKIP_VAR: &str = "VST_WINDOW_SKIP_X_TESTS";
+
+/// How long to wait for the X server to reflect changes made by other clients.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A running X server, along with a connection to it that tests can use to inspect its state.
+pub struct XServer {
+    pub connection: RustConnection,
+    pub screen_num: usize,
+    xvfb: Option<Child>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl XServer {
+    /// Connects to the X server at `DISPLAY`, or starts a new `Xvfb` server and points `DISPLAY`
+    /// to it. If no X server is available, this panics, or returns `None` to skip the test if
+    /// `VST_WINDOW_SKIP_X_TESTS` is set.
+    pub fn start() -> Option<Self> {
+        Self::start_with_args(&[])
+    }
+
+    /// Like `start`, but passes additional arguments to `Xvfb`, e.g. to disable extensions. If
+    /// any arguments are given, `DISPLAY` is ignored and `Xvfb` is always started.
+    pub fn start_with_args(args: &[&str]) -> Option<Self> {
+        let lock = SERVER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let original_display = ORIGINAL_DISPLAY.get_or_init(|| std::env::var_os("DISPLAY"));
+
+        if let (Some(display), true) = (original_display, args.is_empty()) {
+            std::env::set_var("DISPLAY", display);
+            let (connection, screen_num) = match x11rb::connect(None) {
+                Ok(connection) => connection,
+                Err(e) => return unavailable(&format!("could not connect to DISPLAY: {}", e)),
+            };
+            return Some(Self {
+                connection,
+                screen_num,
+                xvfb: None,
+                _lock: lock,
+            });
+        }
+
+        for display_num in 99..199 {
+            if std::path::Path::new(&format!("/tmp/.X11-unix/X{}", display_num)).exists() {
+                continue;
+            }
+            let display = format!(":{}", display_num);
+            let mut xvfb = match Command::new("Xvfb")
+                .args([
+                    display.as_str(),
+                    "-screen",
+                    "0",
+                    "1024x768x24",
+                    "-nolisten",
+                    "tcp",
+                ])
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+            {
+                Ok(xvfb) => xvfb,
+                Err(_) => return unavailable("DISPLAY is not set and Xvfb could not be started"),
+            };
+
+            let start = Instant::now();
+            while start.elapsed() < SETTLE_TIMEOUT {
+                if let Ok(Some(_)) = xvfb.try_wait() {
+                    break;
+                }
+                if let Ok((connection, screen_num)) = x11rb::connect(Some(&display)) {
+                    std::env::set_var("DISPLAY", &display);
+                    return Some(Self {
+                        connection,
+                        screen_num,
+                        xvfb: Some(xvfb),
+                        _lock: lock,
+                    });
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            let _ = xvfb.kill();
+            let _ = xvfb.wait();
+        }
+
+        unavailable("could not find a free display for Xvfb")
+    }
+
+    /// Creates and maps a top-level window, standing in for the window a host would provide to
+    /// `Editor::open`.
+    pub fn create_parent_window(&self, size_xy: (i32, i32)) -> Window {
+        let screen = &self.connection.setup().roots[self.screen_num];
+        let window = self.connection.generate_id().unwrap();
+        self.connection
+            .create_window(
+                COPY_FROM_PARENT as u8,
+                window,
+                screen.root,
+                0,
+                0,
+                size_xy.0 as u16,
+                size_xy.1 as u16,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                screen.root_visual,
+                &CreateWindowAux::new().background_pixel(screen.white_pixel),
+            )
+            .unwrap();
+        self.connection.map_window(window).unwrap();
+        self.sync();
+        window
+    }
+
+    /// Creates an unmapped, input-only window, for tests that act as other clients exchanging data
+    /// with the editor.
+    pub fn create_hidden_window(&self) -> Window {
+        let screen = &self.connection.setup().roots[self.screen_num];
+        let window = self.connection.generate_id().unwrap();
+        self.connection
+            .create_window(
+                COPY_FROM_PARENT as u8,
+                window,
+                screen.root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_ONLY,
+                screen.root_visual,
+                &CreateWindowAux::new(),
+            )
+            .unwrap();
+        self.sync();
+        window
+    }
+
+    /// Returns the atom for `name`, interning it if necessary.
+    pub fn atom(&self, name: &str) -> u32 {
+        self.connection
+            .intern_atom(false, name.as_bytes())
+            .unwrap()
+            .reply()
+            .unwrap()
+            .atom
+    }
+
+    /// Blocks until the server has processed all requests sent on the test connection.
+    pub fn sync(&self) {
+        self.connection.get_input_focus().unwrap().reply().unwrap();
+    }
+
+    /// Waits until the server has mapped the given window, which may have been created by another
+    /// client.
+    pub fn wait_until_viewable(&self, window: Window) {
+        assert!(wait_until(|| {
+            self.connection
+                .get_window_attributes(window)
+                .unwrap()
+                .reply()
+                .map(|attributes| attributes.map_state == MapState::VIEWABLE)
+                .unwrap_or(false)
+        }));
+    }
+
+    /// Returns the number of resources of each type (e.g. `"WINDOW"`, `"GC"`) owned by the client
+    /// that allocated the given resource id, or `None` if that client is no longer connected.
+    pub fn client_resource_counts(&self, xid: u32) -> Option<HashMap<String, u32>> {
+        let clients = self
+            .connection
+            .res_query_clients()
+            .ok()?
+            .reply()
+            .ok()?
+            .clients;
+        clients
+            .iter()
+            .find(|client| xid & !client.resource_mask == client.resource_base)?;
+        let types = self
+            .connection
+            .res_query_client_resources(xid)
+            .ok()?
+            .reply()
+            .ok()?
+            .types;
+        Some(
+            types
+                .into_iter()
+                .map(|t| (self.atom_name(t.resource_type), t.count))
+                .collect(),
+        )
+    }
+
+    /// Returns the number of resources of each type owned by all clients connected to the server,
+    /// along with the number of connected clients under the `"CLIENTS"` key.
+    pub fn total_resource_counts(&self) -> HashMap<String, u32> {
+        let clients = self
+            .connection
+            .res_query_clients()
+            .unwrap()
+            .reply()
+            .unwrap()
+            .clients;
+        let mut totals = HashMap::new();
+        totals.insert("CLIENTS".to_string(), clients.len() as u32);
+        for client in clients {
+            let types = match self
+                .connection
+                .res_query_client_resources(client.resource_base)
+                .unwrap()
+                .reply()
+            {
+                Ok(reply) => reply.types,
+                // The client disconnected in the meantime.
+                Err(_) => continue,
+            };
+            for t in types {
+                *totals.entry(self.atom_name(t.resource_type)).or_insert(0) += t.count;
+            }
+        }
+        totals
+    }
+
+    /// Warps the pointer to the given coordinates relative to `window`, using the XTEST extension.
+    pub fn move_pointer(&self, window: Window, x: i16, y: i16) {
+        let root = self.connection.setup().roots[self.screen_num].root;
+        let position = self
+            .connection
+            .translate_coordinates(window, root, x, y)
+            .unwrap()
+            .reply()
+            .unwrap();
+        self.connection
+            .xtest_fake_input(
+                MOTION_NOTIFY_EVENT,
+                0,
+                x11rb::CURRENT_TIME,
+                root,
+                position.dst_x,
+                position.dst_y,
+                0,
+            )
+            .unwrap();
+        self.sync();
+    }
+
+    /// Returns whether another client has grabbed the pointer, by trying to grab it from the test
+    /// connection.
+    pub fn is_pointer_grabbed(&self) -> bool {
+        let root = self.connection.setup().roots[self.screen_num].root;
+        let status = self
+            .connection
+            .grab_pointer(
+                false,
+                root,
+                EventMask::NO_EVENT,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+                x11rb::NONE,
+                x11rb::NONE,
+                x11rb::CURRENT_TIME,
+            )
+            .unwrap()
+            .reply()
+            .unwrap()
+            .status;
+        if status == GrabStatus::SUCCESS {
+            self.connection.ungrab_pointer(x11rb::CURRENT_TIME).unwrap();
+            self.sync();
+        }
+        status == GrabStatus::ALREADY_GRABBED
+    }
+
+    /// Presses the given pointer button, using the XTEST extension.
+    pub fn press_button(&self, button: u8) {
+        self.fake_input(BUTTON_PRESS_EVENT, button);
+    }
+
+    /// Releases the given pointer button, using the XTEST extension.
+    pub fn release_button(&self, button: u8) {
+        self.fake_input(BUTTON_RELEASE_EVENT, button);
+    }
+
+    /// Presses the key with the given keycode, using the XTEST extension. Key events are sent to
+    /// the focused window.
+    pub fn press_key(&self, keycode: u8) {
+        self.fake_input(KEY_PRESS_EVENT, keycode);
+    }
+
+    /// Releases the key with the given keycode, using the XTEST extension.
+    pub fn release_key(&self, keycode: u8) {
+        self.fake_input(KEY_RELEASE_EVENT, keycode);
+    }
+
+    /// Presses and releases the key with the given keycode.
+    pub fn type_key(&self, keycode: u8) {
+        self.press_key(keycode);
+        self.release_key(keycode);
+    }
+
+    fn fake_input(&self, type_: u8, detail: u8) {
+        self.connection
+            .xtest_fake_input(type_, detail, x11rb::CURRENT_TIME, x11rb::NONE, 0, 0, 0)
+            .unwrap();
+        self.sync();
+    }
+
+    /// Reads back the contents of a window as `0x00RRGGBB` pixels, assuming a 24 or 32 bit deep
+    /// TrueColor visual.
+    pub fn get_pixels(&self, window: Window, width: u16, height: u16) -> Vec<u32> {
+        let reply = self
+            .connection
+            .get_image(ImageFormat::Z_PIXMAP, window, 0, 0, width, height, !0)
+            .unwrap()
+            .reply()
+            .unwrap();
+        let msb_first = self.connection.setup().image_byte_order == ImageOrder::MSB_FIRST;
+        reply
+            .data
+            .chunks_exact(4)
+            .map(|bytes| {
+                let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                let pixel = if msb_first {
+                    u32::from_be_bytes(bytes)
+                } else {
+                    u32::from_le_bytes(bytes)
+                };
+                pixel & 0x00ff_ffff
+            })
+            .collect()
+    }
+
+    fn atom_name(&self, atom: u32) -> String {
+        let reply = self
+            .connection
+            .get_atom_name(atom)
+            .unwrap()
+            .reply()
+            .unwrap();
+        String::from_utf8_lossy(&reply.name).into_owned()
+    }
+}
+
+impl Drop for XServer {
+    fn drop(&mut self) {
+        if let Some(xvfb) = &mut self.xvfb {
+            let _ = xvfb.kill();
+            let _ = xvfb.wait();
+        }
+    }
+}
+
+/// Reports that no X server is available, skipping the test if `VST_WINDOW_SKIP_X_TESTS` is set
+/// and failing it otherwise.
+fn unavailable(reason: &str) -> Option<XServer> {
+    if std::env::var_os(SKIP_VAR).is_some() {
+        eprintln!("Skipping test: {}", reason);
+        return None;
+    }
+    panic!(
+        "{}; set {} to skip tests that need an X server",
+        reason, SKIP_VAR
+    );
+}
+
+/// An editor opened by `open_editor`, with the X11 ids of its window and of the parent window
+/// standing in for the host's.
+pub struct Editor {
+    pub parent: Window,
+    pub window: vst_window::EditorWindow,
+    pub event_source: vst_window::EventSource,
+    pub child: Window,
+}
+
+/// Opens an editor of size `size_xy` in a new parent window, waiting until it has been mapped.
+pub fn open_editor(server: &XServer, size_xy: (i32, i32)) -> Editor {
+    let parent = server.create_parent_window(size_xy);
+    let (window, event_source) = vst_window::setup(parent as *mut _, size_xy);
+    let child = window_id(&window);
+    server.wait_until_viewable(child);
+    Editor {
+        parent,
+        window,
+        event_source,
+        child,
+    }
+}
+
+/// Repeatedly evaluates `condition` until it returns `true`, for up to a few seconds. Other
+/// clients' requests are processed by the X server asynchronously to the test's own.
+pub fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < SETTLE_TIMEOUT {
+        if condition() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    condition()
+}
+
+/// Polls `event_source` until `count` events have been received, or until a few seconds have
+/// passed, returning all received events.
+pub fn collect_events(
+    event_source: &vst_window::EventSource,
+    count: usize,
+) -> Vec<vst_window::WindowEvent> {
+    let mut events = vec![];
+    wait_until(|| {
+        while let Some(event) = event_source.poll_event() {
+            events.push(event);
+        }
+        events.len() >= count
+    });
+    events
+}
+
+/// Returns the X11 window id of an `EditorWindow`.
+pub fn window_id(window: &vst_window::EditorWindow) -> u32 {
+    window.x11_window_id().expect("X11 window id")
+}
@@ -0,0 +1,556 @@
+//! Checks both sides of the XDND protocol, with the test acting as the other side of the drag.
+
+#![cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+
+mod common;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use common::{collect_events, open_editor, wait_until, Editor, XServer};
+use vst_window::{DragData, EventSource, MouseButton, WindowEvent};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ClientMessageEvent, ConnectionExt as _, CreateWindowAux, EventMask, PropMode,
+    SelectionNotifyEvent, Window, WindowClass, SELECTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::Event;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_FROM_PARENT, CURRENT_TIME};
+
+const SIZE_XY: (i32, i32) = (300, 200);
+
+/// A drag source living on the test's own connection.
+struct Source<'a> {
+    server: &'a XServer,
+    window: Window,
+    target: Window,
+}
+
+impl<'a> Source<'a> {
+    fn new(server: &'a XServer, target: Window) -> Self {
+        Self {
+            server,
+            window: server.create_hidden_window(),
+            target,
+        }
+    }
+
+    fn atom(&self, name: &str) -> u32 {
+        self.server.atom(name)
+    }
+
+    fn send(&self, type_: &str, data: [u32; 5]) {
+        let message = ClientMessageEvent::new(32, self.target, self.atom(type_), data);
+        self.server
+            .connection
+            .send_event(false, self.target, EventMask::NO_EVENT, message)
+            .unwrap();
+        self.server.sync();
+    }
+
+    fn enter(&self) {
+        let uri_list = self.atom("text/uri-list");
+        self.send("XdndEnter", [self.window, 5 << 24, uri_list, 0, 0]);
+    }
+
+    fn position(&self, x: i16, y: i16) {
+        let root = self.server.connection.setup().roots[self.server.screen_num].root;
+        let position = self
+            .server
+            .connection
+            .translate_coordinates(self.target, root, x, y)
+            .unwrap()
+            .reply()
+            .unwrap();
+        let root_xy = ((position.dst_x as u32) << 16) | position.dst_y as u32;
+        let action = self.atom("XdndActionCopy");
+        self.send(
+            "XdndPosition",
+            [self.window, 0, root_xy, CURRENT_TIME, action],
+        );
+    }
+
+    /// Waits for a `ClientMessage` of the given type from the target, returning its data.
+    fn expect_message(&self, type_: &str) -> [u32; 5] {
+        let type_ = self.atom(type_);
+        let mut data = None;
+        assert!(wait_until(|| {
+            while let Some(event) = self.server.connection.poll_for_event().unwrap() {
+                if let Event::ClientMessage(message) = event {
+                    if message.type_ == type_ {
+                        data = Some(message.data.as_data32());
+                    }
+                }
+            }
+            data.is_some()
+        }));
+        data.unwrap()
+    }
+
+    /// Answers the target's request for the dragged data with the given `text/uri-list`.
+    fn provide_uri_list(&self, uri_list: &[u8]) {
+        let mut handled = false;
+        assert!(wait_until(|| {
+            while let Some(event) = self.server.connection.poll_for_event().unwrap() {
+                if let Event::SelectionRequest(request) = event {
+                    self.server
+                        .connection
+                        .change_property8(
+                            PropMode::REPLACE,
+                            request.requestor,
+                            request.property,
+                            request.target,
+                            uri_list,
+                        )
+                        .unwrap();
+                    let notify = SelectionNotifyEvent {
+                        response_type: SELECTION_NOTIFY_EVENT,
+                        sequence: 0,
+                        time: request.time,
+                        requestor: request.requestor,
+                        selection: request.selection,
+                        target: request.target,
+                        property: request.property,
+                    };
+                    self.server
+                        .connection
+                        .send_event(false, request.requestor, EventMask::NO_EVENT, notify)
+                        .unwrap();
+                    self.server.sync();
+                    handled = true;
+                }
+            }
+            handled
+        }));
+    }
+}
+
+#[test]
+fn dropped_files_are_reported() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    while event_source.poll_event().is_some() {}
+
+    event_source.set_file_drop_enabled(true);
+    let source = Source::new(&server, child);
+    let xdnd_aware = source.atom("XdndAware");
+    assert!(wait_until(|| {
+        let reply = server
+            .connection
+            .get_property(false, child, xdnd_aware, AtomEnum::ATOM, 0, 1)
+            .unwrap()
+            .reply()
+            .unwrap();
+        reply.value32().and_then(|mut v| v.next()) == Some(5)
+    }));
+
+    source.enter();
+    source.position(150, 100);
+    assert_eq!(
+        collect_events(&event_source, 1),
+        vec![WindowEvent::FileHovered(0.5, 0.5)]
+    );
+    let status = source.expect_message("XdndStatus");
+    assert_eq!(status[0], child);
+    assert_eq!(status[1] & 1, 1);
+    assert_eq!(status[4], source.atom("XdndActionCopy"));
+
+    server
+        .connection
+        .set_selection_owner(source.window, source.atom("XdndSelection"), CURRENT_TIME)
+        .unwrap();
+    source.send("XdndDrop", [source.window, 0, CURRENT_TIME, 0, 0]);
+    // Polling sends the request for the dropped data.
+    assert_eq!(event_source.poll_event(), None);
+    source.provide_uri_list(b"# comment\r\nfile:///tmp/a%20b.wav\r\nfile://localhost/x/y\r\n");
+
+    assert_eq!(
+        collect_events(&event_source, 1),
+        vec![WindowEvent::FileDropped(vec![
+            PathBuf::from("/tmp/a b.wav"),
+            PathBuf::from("/x/y"),
+        ])]
+    );
+    let finished = source.expect_message("XdndFinished");
+    assert_eq!(finished[0], child);
+    assert_eq!(finished[1] & 1, 1);
+}
+
+#[test]
+fn rejected_drops_are_cancelled() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    while event_source.poll_event().is_some() {}
+
+    event_source.set_file_drop_enabled(true);
+    let source = Source::new(&server, child);
+    source.enter();
+    source.position(30, 20);
+    assert_eq!(
+        collect_events(&event_source, 1),
+        vec![WindowEvent::FileHovered(0.1, 0.1)]
+    );
+    event_source.set_drop_accepted(false);
+    let status = source.expect_message("XdndStatus");
+    assert_eq!(status[1] & 1, 0);
+    assert_eq!(status[4], x11rb::NONE);
+
+    source.send("XdndLeave", [source.window, 0, 0, 0, 0]);
+    assert_eq!(
+        collect_events(&event_source, 1),
+        vec![WindowEvent::FileHoverCancelled]
+    );
+}
+
+#[test]
+fn drops_are_ignored_unless_enabled() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let source = Source::new(&server, child);
+    source.enter();
+    source.position(150, 100);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    while let Some(event) = event_source.poll_event() {
+        assert!(!matches!(event, WindowEvent::FileHovered(..)));
+    }
+}
+
+/// A drop target living on the test's own connection, in a top-level window to the right of the
+/// editor's parent window.
+struct Target<'a> {
+    server: &'a XServer,
+    window: Window,
+    /// Messages received while waiting for another one.
+    messages: RefCell<VecDeque<(u32, [u32; 5])>>,
+}
+
+impl<'a> Target<'a> {
+    /// The position of the target window relative to the root window.
+    const X: i16 = 400;
+
+    fn new(server: &'a XServer) -> Self {
+        let screen = &server.connection.setup().roots[server.screen_num];
+        let window = server.connection.generate_id().unwrap();
+        server
+            .connection
+            .create_window(
+                COPY_FROM_PARENT as u8,
+                window,
+                screen.root,
+                Self::X,
+                0,
+                200,
+                200,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                screen.root_visual,
+                &CreateWindowAux::new(),
+            )
+            .unwrap();
+        let xdnd_aware = server.atom("XdndAware");
+        server
+            .connection
+            .change_property32(PropMode::REPLACE, window, xdnd_aware, AtomEnum::ATOM, &[5])
+            .unwrap();
+        server.connection.map_window(window).unwrap();
+        server.sync();
+        server.wait_until_viewable(window);
+        Self {
+            server,
+            window,
+            messages: RefCell::default(),
+        }
+    }
+
+    fn send(&self, editor: Window, type_: &str, data: [u32; 5]) {
+        let message = ClientMessageEvent::new(32, editor, self.server.atom(type_), data);
+        self.server
+            .connection
+            .send_event(false, editor, EventMask::NO_EVENT, message)
+            .unwrap();
+        self.server.sync();
+    }
+
+    /// Waits for the next `ClientMessage` from the editor, which must be of the given type,
+    /// returning its data. The editor's event source is polled in the meantime, and its events
+    /// are added to `events`.
+    fn expect_message(
+        &self,
+        event_source: &EventSource,
+        events: &mut Vec<WindowEvent>,
+        type_: &str,
+    ) -> [u32; 5] {
+        assert!(wait_until(|| {
+            while let Some(event) = event_source.poll_event() {
+                events.push(event);
+            }
+            while let Some(event) = self.server.connection.poll_for_event().unwrap() {
+                if let Event::ClientMessage(message) = event {
+                    let message = (message.type_, message.data.as_data32());
+                    self.messages.borrow_mut().push_back(message);
+                }
+            }
+            !self.messages.borrow().is_empty()
+        }));
+        let (received, data) = self.messages.borrow_mut().pop_front().unwrap();
+        assert_eq!(received, self.server.atom(type_));
+        data
+    }
+
+    /// Requests the dragged data as `target`, polling the editor's event source until it has
+    /// been stored in a property of the target window.
+    fn request_data(&self, event_source: &EventSource, target: &str) -> Vec<u8> {
+        let property = self.server.atom("DROPPED");
+        self.server
+            .connection
+            .convert_selection(
+                self.window,
+                self.server.atom("XdndSelection"),
+                self.server.atom(target),
+                property,
+                CURRENT_TIME,
+            )
+            .unwrap();
+        self.server.sync();
+        let mut notified = false;
+        assert!(wait_until(|| {
+            while event_source.poll_event().is_some() {}
+            while let Some(event) = self.server.connection.poll_for_event().unwrap() {
+                if let Event::SelectionNotify(notify) = event {
+                    assert_eq!(notify.property, property);
+                    notified = true;
+                }
+            }
+            notified
+        }));
+        self.server
+            .connection
+            .get_property(true, self.window, property, AtomEnum::ANY, 0, 1 << 16)
+            .unwrap()
+            .reply()
+            .unwrap()
+            .value
+    }
+}
+
+#[test]
+fn files_can_be_dragged_out_of_the_editor() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    let target = Target::new(&server);
+    let action_copy = server.atom("XdndActionCopy");
+
+    server.move_pointer(child, 150, 100);
+    server.press_button(1);
+    assert_eq!(
+        collect_events(&event_source, 2),
+        vec![
+            WindowEvent::CursorMovement(0.5, 0.5),
+            WindowEvent::MouseClick(MouseButton::Left),
+        ]
+    );
+    let files = DragData::Files(vec![
+        PathBuf::from("/tmp/clip 1.wav"),
+        PathBuf::from("clip.wav"),
+    ]);
+    assert!(event_source.start_drag(files.clone()));
+    assert!(!event_source.start_drag(files));
+
+    server.move_pointer(target.window, 100, 50);
+    let mut events = vec![];
+    let enter = target.expect_message(&event_source, &mut events, "XdndEnter");
+    assert_eq!(enter[0], child);
+    assert_eq!(enter[1] >> 24, 5);
+    assert_eq!(enter[2], server.atom("text/uri-list"));
+    let position = target.expect_message(&event_source, &mut events, "XdndPosition");
+    assert_eq!(position[2], ((Target::X as u32 + 100) << 16) | 50);
+    assert_eq!(position[4], action_copy);
+    target.send(child, "XdndStatus", [target.window, 1, 0, 0, action_copy]);
+
+    server.release_button(1);
+    let drop = target.expect_message(&event_source, &mut events, "XdndDrop");
+    assert_eq!(drop[0], child);
+    // Relative paths are resolved against the current directory, the package's root.
+    let current_dir = std::env::current_dir().unwrap();
+    assert_eq!(
+        target.request_data(&event_source, "text/uri-list"),
+        format!(
+            "file:///tmp/clip%201.wav\r\nfile://{}/clip.wav\r\n",
+            current_dir.display()
+        )
+        .into_bytes()
+    );
+    target.send(child, "XdndFinished", [target.window, 1, action_copy, 0, 0]);
+
+    events.extend(collect_events(&event_source, 1));
+    assert_eq!(
+        events,
+        vec![
+            WindowEvent::CursorMovement(500. / 300., 50. / 200.),
+            WindowEvent::MouseRelease(MouseButton::Left),
+            WindowEvent::DragEnded { accepted: true },
+        ]
+    );
+}
+
+#[test]
+fn drags_rejected_by_the_target_are_cancelled() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    let target = Target::new(&server);
+
+    server.move_pointer(child, 150, 100);
+    server.press_button(1);
+    collect_events(&event_source, 2);
+    assert!(event_source.start_drag(DragData::Mime {
+        mime_type: "text/plain;charset=utf-8".to_string(),
+        data: b"-3.5 dB".to_vec(),
+    }));
+
+    server.move_pointer(target.window, 100, 50);
+    let mut events = vec![];
+    let enter = target.expect_message(&event_source, &mut events, "XdndEnter");
+    assert_eq!(enter[2], server.atom("text/plain;charset=utf-8"));
+    target.expect_message(&event_source, &mut events, "XdndPosition");
+    // Releasing before the target has answered waits for its answer.
+    server.release_button(1);
+    target.send(child, "XdndStatus", [target.window, 0, 0, 0, 0]);
+    target.expect_message(&event_source, &mut events, "XdndLeave");
+
+    events.extend(collect_events(&event_source, 1));
+    assert_eq!(
+        events.last(),
+        Some(&WindowEvent::DragEnded { accepted: false })
+    );
+    assert!(events.contains(&WindowEvent::MouseRelease(MouseButton::Left)));
+}
+
+#[test]
+fn drops_that_the_target_never_finishes_time_out() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    let target = Target::new(&server);
+    let action_copy = server.atom("XdndActionCopy");
+    let files = DragData::Files(vec![PathBuf::from("/tmp/clip 1.wav")]);
+
+    server.move_pointer(child, 150, 100);
+    server.press_button(1);
+    collect_events(&event_source, 2);
+    assert!(event_source.start_drag(files.clone()));
+
+    server.move_pointer(target.window, 100, 50);
+    let mut events = vec![];
+    target.expect_message(&event_source, &mut events, "XdndEnter");
+    target.expect_message(&event_source, &mut events, "XdndPosition");
+    target.send(child, "XdndStatus", [target.window, 1, 0, 0, action_copy]);
+    server.release_button(1);
+    target.expect_message(&event_source, &mut events, "XdndDrop");
+    // No new drag can start until the target has finished with the drop, or been given up on.
+    assert!(!event_source.start_drag(files.clone()));
+    assert!(event_source.poll_timeout().is_some());
+
+    assert!(wait_until(|| {
+        while let Some(event) = event_source.poll_event() {
+            events.push(event);
+        }
+        events.contains(&WindowEvent::DragEnded { accepted: false })
+    }));
+    assert!(event_source.start_drag(files));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn event_streams_wake_up_when_drops_time_out() {
+    use futures_core::Stream;
+    use std::pin::Pin;
+
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    let target = Target::new(&server);
+    let action_copy = server.atom("XdndActionCopy");
+    let files = DragData::Files(vec![PathBuf::from("/tmp/clip 1.wav")]);
+
+    server.move_pointer(child, 150, 100);
+    server.press_button(1);
+    collect_events(&event_source, 2);
+    assert!(event_source.start_drag(files));
+
+    server.move_pointer(target.window, 100, 50);
+    let mut events = vec![];
+    target.expect_message(&event_source, &mut events, "XdndEnter");
+    target.expect_message(&event_source, &mut events, "XdndPosition");
+    target.send(child, "XdndStatus", [target.window, 1, 0, 0, action_copy]);
+    server.release_button(1);
+    target.expect_message(&event_source, &mut events, "XdndDrop");
+
+    // The target never finishes, so the stream has to wake up by itself to give up on it.
+    let mut stream = event_source.into_stream().unwrap();
+    async_io::block_on(async {
+        loop {
+            let event = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await;
+            match event {
+                Some(WindowEvent::DragEnded { accepted }) => break assert!(!accepted),
+                Some(_) => {}
+                None => panic!("the stream ended before the drag"),
+            }
+        }
+    });
+}
@@ -0,0 +1,501 @@
+//! Checks the `WindowEvent`s produced by an `EventSource` in response to simulated input.
+
+#![cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{collect_events, open_editor, wait_until, window_id, Editor, XServer};
+use vst_window::{MouseButton, WindowEvent};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt as _, InputFocus};
+use x11rb::CURRENT_TIME;
+
+const SIZE_XY: (i32, i32) = (300, 200);
+
+#[test]
+fn cursor_movement_is_normalised_to_window_size() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    server.move_pointer(child, 75, 150);
+    server.move_pointer(child, 300, 0);
+
+    assert_eq!(
+        collect_events(&event_source, 2),
+        vec![
+            WindowEvent::CursorMovement(0.25, 0.75),
+            WindowEvent::CursorMovement(1., 0.),
+        ]
+    );
+}
+
+#[test]
+fn cursor_movement_outside_window_is_reported_during_drag() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    server.move_pointer(child, 150, 100);
+    server.press_button(1);
+    server.move_pointer(child, 450, 300);
+    server.release_button(1);
+
+    assert_eq!(
+        collect_events(&event_source, 4),
+        vec![
+            WindowEvent::CursorMovement(0.5, 0.5),
+            WindowEvent::MouseClick(MouseButton::Left),
+            WindowEvent::CursorMovement(1.5, 1.5),
+            WindowEvent::MouseRelease(MouseButton::Left),
+        ]
+    );
+}
+
+#[test]
+fn grabbed_pointer_reports_movement_outside_window() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    assert!(window.grab_pointer());
+    assert!(server.is_pointer_grabbed());
+    server.move_pointer(child, 450, 300);
+    assert_eq!(
+        collect_events(&event_source, 1),
+        vec![WindowEvent::CursorMovement(1.5, 1.5)]
+    );
+
+    window.ungrab_pointer();
+    assert!(wait_until(|| !server.is_pointer_grabbed()));
+}
+
+#[test]
+fn capture_on_press_lasts_until_all_buttons_are_released() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    event_source.set_capture_on_press(true);
+
+    server.move_pointer(child, 150, 100);
+    server.press_button(1);
+    server.press_button(3);
+    server.move_pointer(child, 450, 300);
+    server.release_button(1);
+    assert_eq!(
+        collect_events(&event_source, 5),
+        vec![
+            WindowEvent::CursorMovement(0.5, 0.5),
+            WindowEvent::MouseClick(MouseButton::Left),
+            WindowEvent::MouseClick(MouseButton::Right),
+            WindowEvent::CursorMovement(1.5, 1.5),
+            WindowEvent::MouseRelease(MouseButton::Left),
+        ]
+    );
+    assert!(server.is_pointer_grabbed());
+
+    server.move_pointer(child, 600, 400);
+    server.release_button(3);
+    assert_eq!(
+        collect_events(&event_source, 2),
+        vec![
+            WindowEvent::CursorMovement(2.0, 2.0),
+            WindowEvent::MouseRelease(MouseButton::Right),
+        ]
+    );
+    assert!(wait_until(|| !server.is_pointer_grabbed()));
+}
+
+#[test]
+fn mouse_buttons_are_mapped() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    server.move_pointer(child, 10, 10);
+    for button in 1..=5 {
+        server.press_button(button);
+        server.release_button(button);
+    }
+
+    assert_eq!(
+        collect_events(&event_source, 7),
+        vec![
+            WindowEvent::CursorMovement(10. / 300., 10. / 200.),
+            WindowEvent::MouseClick(MouseButton::Left),
+            WindowEvent::MouseRelease(MouseButton::Left),
+            WindowEvent::MouseClick(MouseButton::Middle),
+            WindowEvent::MouseRelease(MouseButton::Middle),
+            WindowEvent::MouseClick(MouseButton::Right),
+            WindowEvent::MouseRelease(MouseButton::Right),
+        ]
+    );
+}
+
+#[test]
+fn unrelated_events_do_not_end_polling() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    server.move_pointer(child, 150, 100);
+    server.press_button(1);
+    server
+        .connection
+        .clear_area(true, child, 0, 0, 0, 0)
+        .unwrap();
+    server.connection.flush().unwrap();
+    server.release_button(1);
+    std::thread::sleep(Duration::from_millis(200));
+
+    let mut events = vec![];
+    while let Some(event) = event_source.poll_event() {
+        events.push(event);
+    }
+    assert_eq!(
+        events,
+        vec![
+            WindowEvent::CursorMovement(0.5, 0.5),
+            WindowEvent::MouseClick(MouseButton::Left),
+            WindowEvent::MouseRelease(MouseButton::Left),
+        ]
+    );
+}
+
+#[test]
+fn held_keys_repeat_presses_and_are_released_once() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    server
+        .connection
+        .set_input_focus(InputFocus::PARENT, child, CURRENT_TIME)
+        .unwrap();
+
+    // The right arrow key in `Xvfb`'s default keymap, held for longer than the auto-repeat delay.
+    let keycode = 114;
+    server.press_key(keycode);
+    std::thread::sleep(Duration::from_millis(800));
+    server.release_key(keycode);
+    std::thread::sleep(Duration::from_millis(200));
+
+    let mut events = vec![];
+    while let Some(event) = event_source.poll_event() {
+        events.push(event);
+    }
+    let keycode = u32::from(keycode);
+    assert_eq!(
+        events.first(),
+        Some(&WindowEvent::KeyPress {
+            keycode,
+            repeat: false
+        })
+    );
+    assert_eq!(events.last(), Some(&WindowEvent::KeyRelease { keycode }));
+    for event in &events[1..events.len() - 1] {
+        assert_eq!(
+            event,
+            &WindowEvent::KeyPress {
+                keycode,
+                repeat: true
+            }
+        );
+    }
+}
+
+#[test]
+fn event_source_can_be_dropped_before_window() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let parent = server.create_parent_window(SIZE_XY);
+
+    let (window, event_source) = vst_window::setup(parent as *mut _, SIZE_XY);
+    let child = window_id(&window);
+    drop(event_source);
+
+    // The window must still be alive on the server.
+    server
+        .connection
+        .get_window_attributes(child)
+        .unwrap()
+        .reply()
+        .unwrap();
+    drop(window);
+
+    let (window, event_source) = vst_window::setup(parent as *mut _, SIZE_XY);
+    let child = window_id(&window);
+    server.wait_until_viewable(child);
+    server.move_pointer(child, 150, 100);
+    assert_eq!(
+        collect_events(&event_source, 1),
+        vec![WindowEvent::CursorMovement(0.5, 0.5)]
+    );
+}
+
+#[test]
+fn window_can_be_dropped_before_event_source() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let parent = server.create_parent_window(SIZE_XY);
+
+    let (window, event_source) = vst_window::setup(parent as *mut _, SIZE_XY);
+    let child = window_id(&window);
+    server.wait_until_viewable(child);
+    drop(window);
+    assert!(wait_until(|| server
+        .connection
+        .get_window_attributes(child)
+        .unwrap()
+        .reply()
+        .is_err()));
+
+    // Input over the parent must not reach the destroyed window, and polling must not fail.
+    server.move_pointer(parent, 150, 100);
+    server.press_button(1);
+    server.release_button(1);
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(event_source.poll_event(), None);
+    drop(event_source);
+}
+
+#[test]
+fn frame_events_are_emitted_at_requested_rate() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    event_source.set_frame_rate(Some(50.));
+    let frames: Vec<(Duration, Duration)> = collect_events(&event_source, 5)
+        .into_iter()
+        .filter_map(|event| match event {
+            WindowEvent::Frame { timestamp, delta } => Some((timestamp, delta)),
+            _ => None,
+        })
+        .collect();
+    assert!(frames.len() >= 5);
+    for pair in frames.windows(2) {
+        assert!(pair[1].0 > pair[0].0);
+        assert_eq!(pair[1].1, pair[1].0 - pair[0].0);
+    }
+    // Individual frames are timestamped when polled, so only the average interval is close to
+    // the requested 20 ms.
+    let average = (frames.last().unwrap().0 - frames[0].0) / (frames.len() as u32 - 1);
+    assert!(
+        average >= Duration::from_millis(15) && average <= Duration::from_millis(40),
+        "{:?}",
+        average
+    );
+
+    // Polling slowly skips frames rather than queueing them up. Another frame may arrive while the
+    // queue is being drained.
+    std::thread::sleep(Duration::from_millis(200));
+    let mut queued = vec![];
+    while let Some(event) = event_source.poll_event() {
+        queued.push(event);
+    }
+    assert!(queued.len() <= 2, "{:?}", queued);
+
+    event_source.set_frame_rate(None);
+    while event_source.poll_event().is_some() {}
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(event_source.poll_event(), None);
+
+    // Intervals too long to represent never elapse.
+    event_source.set_frame_rate(Some(1e-300));
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(event_source.poll_event(), None);
+}
+
+#[test]
+fn waker_sends_user_events_from_other_threads() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    while event_source.poll_event().is_some() {}
+
+    let waker = event_source.waker();
+    assert_send_sync(&waker);
+    std::thread::spawn(move || {
+        waker.wake(1);
+        waker.wake(2);
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(
+        collect_events(&event_source, 2),
+        vec![WindowEvent::User(1), WindowEvent::User(2)]
+    );
+    assert_eq!(event_source.poll_event(), None);
+}
+
+#[test]
+fn waiting_blocks_until_event_or_timeout() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    while event_source.poll_event().is_some() {}
+
+    let start = Instant::now();
+    assert_eq!(
+        event_source.wait_event_timeout(Duration::from_millis(200)),
+        None
+    );
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    let waker = event_source.waker();
+    let start = Instant::now();
+    let thread = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        waker.wake(7);
+    });
+    assert_eq!(event_source.wait_event(), WindowEvent::User(7));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    thread.join().unwrap();
+}
+
+#[test]
+fn connection_fd_becomes_readable_when_woken() {
+    use std::os::unix::io::{AsFd, AsRawFd};
+
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    while event_source.poll_event().is_some() {}
+
+    assert_eq!(event_source.as_fd().as_raw_fd(), event_source.as_raw_fd());
+    let is_readable = || {
+        let mut pollfd = libc::pollfd {
+            fd: event_source.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut pollfd, 1, 0) == 1 }
+    };
+    assert!(!is_readable());
+
+    event_source.waker().wake(3);
+    assert!(wait_until(is_readable));
+    assert_eq!(collect_events(&event_source, 1), vec![WindowEvent::User(3)]);
+}
+
+#[test]
+fn consecutive_motion_is_coalesced_when_enabled() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    while event_source.poll_event().is_some() {}
+    event_source.set_coalesce_motion(true);
+
+    server.move_pointer(child, 30, 20);
+    server.move_pointer(child, 60, 40);
+    server.move_pointer(child, 90, 60);
+    server.press_button(1);
+    server.move_pointer(child, 150, 100);
+    server.move_pointer(child, 300, 200);
+    server.release_button(1);
+    std::thread::sleep(Duration::from_millis(200));
+
+    assert_eq!(
+        event_source.poll_event(),
+        Some(WindowEvent::CursorMovement(0.3, 0.3))
+    );
+    assert_eq!(
+        event_source.coalesced_motion(),
+        vec![(0.1, 0.1), (0.2, 0.2), (0.3, 0.3)]
+    );
+    let mut events = vec![];
+    while let Some(event) = event_source.poll_event() {
+        events.push(event);
+    }
+    assert_eq!(
+        events,
+        vec![
+            WindowEvent::MouseClick(MouseButton::Left),
+            WindowEvent::CursorMovement(1., 1.),
+            WindowEvent::MouseRelease(MouseButton::Left),
+        ]
+    );
+    assert_eq!(event_source.coalesced_motion(), vec![(0.5, 0.5), (1., 1.)]);
+}
@@ -0,0 +1,325 @@
+//! Checks that text composed through an X input method reaches the editor.
+
+#![cfg(all(
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ),
+    feature = "ime"
+))]
+
+mod common;
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use common::{collect_events, wait_until, window_id, XServer};
+use vst_window::WindowEvent;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt as _, InputFocus, KeyPressEvent, KEY_RELEASE_EVENT};
+use x11rb::CURRENT_TIME;
+use xim::x11rb::X11rbServer;
+use xim::{InputStyle, Server, ServerError, ServerHandler, UserInputContext, XimConnections};
+
+const SIZE_XY: (i32, i32) = (300, 200);
+
+const SERVER_NAME: &str = "vst_window_test";
+
+/// `KeyPressMask | KeyReleaseMask`, the events that the fake input method filters.
+const KEY_EVENT_MASK: u32 = 3;
+
+/// Keycodes in `Xvfb`'s default keymap.
+const KEY_A: u8 = 38;
+const KEY_RETURN: u8 = 36;
+/// Not consumed by the fake input method.
+const KEY_ESCAPE: u8 = 9;
+
+#[test]
+fn composed_text_is_received() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let input_method = FakeInputMethod::spawn();
+    let parent = server.create_parent_window(SIZE_XY);
+    std::env::set_var("XMODIFIERS", format!("@im={}", SERVER_NAME));
+    let (window, event_source) = vst_window::setup(parent as *mut _, SIZE_XY);
+    std::env::remove_var("XMODIFIERS");
+    let child = window_id(&window);
+    server.wait_until_viewable(child);
+
+    // The input context is created while the event source is polled, and the position is applied
+    // to it either way.
+    window.set_ime_position(40, 60);
+    assert!(wait_until(|| {
+        while event_source.poll_event().is_some() {}
+        *input_method.spot.lock().unwrap() == Some((40, 60))
+    }));
+
+    server
+        .connection
+        .set_input_focus(InputFocus::PARENT, child, CURRENT_TIME)
+        .unwrap();
+    server.type_key(KEY_A);
+    assert_eq!(
+        collect_events(&event_source, 1),
+        [WindowEvent::ImePreedit {
+            text: "にほん".to_owned(),
+            cursor: "にほん".len(),
+        }]
+    );
+
+    server.type_key(KEY_RETURN);
+    assert_eq!(
+        collect_events(&event_source, 2),
+        [
+            WindowEvent::ImePreedit {
+                text: String::new(),
+                cursor: 0,
+            },
+            WindowEvent::ImeCommit("日本語".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn unconsumed_keys_are_pressed_and_released() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let _input_method = FakeInputMethod::spawn();
+    let parent = server.create_parent_window(SIZE_XY);
+    std::env::set_var("XMODIFIERS", format!("@im={}", SERVER_NAME));
+    let (window, event_source) = vst_window::setup(parent as *mut _, SIZE_XY);
+    std::env::remove_var("XMODIFIERS");
+    let child = window_id(&window);
+    server.wait_until_viewable(child);
+    server
+        .connection
+        .set_input_focus(InputFocus::PARENT, child, CURRENT_TIME)
+        .unwrap();
+
+    // Keys typed before the input context exists aren't forwarded, so wait for it to be created
+    // by typing a key the input method composes with.
+    assert!(wait_until(|| {
+        server.type_key(KEY_A);
+        let mut composed = false;
+        while let Some(event) = event_source.poll_event() {
+            composed |= matches!(event, WindowEvent::ImePreedit { .. });
+        }
+        composed
+    }));
+
+    // Both taps are typed before the input method has sent back the first press.
+    server.type_key(KEY_ESCAPE);
+    server.type_key(KEY_ESCAPE);
+    let press = WindowEvent::KeyPress {
+        keycode: KEY_ESCAPE.into(),
+        repeat: false,
+    };
+    let release = WindowEvent::KeyRelease {
+        keycode: KEY_ESCAPE.into(),
+    };
+    assert_eq!(
+        collect_events(&event_source, 4),
+        [press.clone(), release.clone(), press, release]
+    );
+}
+
+#[test]
+fn popups_dont_connect_to_the_input_method() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let input_method = FakeInputMethod::spawn();
+    let parent = server.create_parent_window(SIZE_XY);
+    std::env::set_var("XMODIFIERS", format!("@im={}", SERVER_NAME));
+    let (window, event_source) = vst_window::setup(parent as *mut _, SIZE_XY);
+    let (_popup, popup_events) = window.create_popup((20, 20), SIZE_XY).unwrap();
+    std::env::remove_var("XMODIFIERS");
+    server.wait_until_viewable(window_id(&window));
+
+    window.set_ime_position(40, 60);
+    assert!(wait_until(|| {
+        while event_source.poll_event().is_some() {}
+        while popup_events.poll_event().is_some() {}
+        *input_method.spot.lock().unwrap() == Some((40, 60))
+    }));
+    // Leaves time for a connection from the popup to reach the server.
+    for _ in 0..10 {
+        while popup_events.poll_event().is_some() {}
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(input_method.connections.load(Ordering::Relaxed), 1);
+}
+
+/// An input method server running on a background thread, which composes "にほん" for any key
+/// and commits it as "日本語" on Return. Escape and all releases are sent back unconsumed.
+struct FakeInputMethod {
+    /// The most recent spot location set by the client.
+    spot: Arc<Mutex<Option<(i16, i16)>>>,
+    /// The number of clients that have connected.
+    connections: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FakeInputMethod {
+    /// Returns once the server has been registered, so that clients can find it.
+    fn spawn() -> Self {
+        let spot = Arc::new(Mutex::new(None));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_sender, ready) = mpsc::channel();
+        let thread = {
+            let spot = Arc::clone(&spot);
+            let connection_count = Arc::clone(&connections);
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                let (connection, screen_num) = x11rb::connect(None).unwrap();
+                let mut server =
+                    X11rbServer::init(&connection, screen_num, SERVER_NAME, xim::ALL_LOCALES)
+                        .unwrap();
+                ready_sender.send(()).unwrap();
+                let mut connections = XimConnections::new();
+                let mut handler = Handler {
+                    spot,
+                    connections: connection_count,
+                };
+                while !stop.load(Ordering::Relaxed) {
+                    match connection.poll_for_event().unwrap() {
+                        Some(event) => {
+                            server
+                                .filter_event(&event, &mut connections, &mut handler)
+                                .unwrap();
+                        }
+                        None => std::thread::sleep(Duration::from_millis(10)),
+                    }
+                }
+            })
+        };
+        ready.recv().unwrap();
+        Self {
+            spot,
+            connections,
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for FakeInputMethod {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Handler {
+    spot: Arc<Mutex<Option<(i16, i16)>>>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl Handler {
+    fn record_spot(&self, user_ic: &UserInputContext<()>) {
+        let spot = user_ic.ic.preedit_spot();
+        *self.spot.lock().unwrap() = Some((spot.x, spot.y));
+    }
+}
+
+impl<S: Server<XEvent = KeyPressEvent>> ServerHandler<S> for Handler {
+    type InputContextData = ();
+    type InputStyleArray = [InputStyle; 1];
+
+    fn new_ic_data(&mut self, _server: &mut S, _style: InputStyle) -> Result<(), ServerError> {
+        Ok(())
+    }
+
+    fn input_styles(&self) -> Self::InputStyleArray {
+        [InputStyle::PREEDIT_CALLBACKS | InputStyle::STATUS_NOTHING]
+    }
+
+    /// Asks for key presses and releases to be forwarded.
+    fn filter_events(&self) -> u32 {
+        KEY_EVENT_MASK
+    }
+
+    fn handle_connect(&mut self, _server: &mut S) -> Result<(), ServerError> {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn handle_create_ic(
+        &mut self,
+        server: &mut S,
+        user_ic: &mut UserInputContext<()>,
+    ) -> Result<(), ServerError> {
+        self.record_spot(user_ic);
+        server.set_event_mask(&user_ic.ic, KEY_EVENT_MASK, 0)
+    }
+
+    fn handle_destroy_ic(
+        &mut self,
+        _server: &mut S,
+        _user_ic: UserInputContext<()>,
+    ) -> Result<(), ServerError> {
+        Ok(())
+    }
+
+    fn handle_reset_ic(
+        &mut self,
+        _server: &mut S,
+        _user_ic: &mut UserInputContext<()>,
+    ) -> Result<String, ServerError> {
+        Ok(String::new())
+    }
+
+    fn handle_set_focus(
+        &mut self,
+        _server: &mut S,
+        _user_ic: &mut UserInputContext<()>,
+    ) -> Result<(), ServerError> {
+        Ok(())
+    }
+
+    fn handle_unset_focus(
+        &mut self,
+        _server: &mut S,
+        _user_ic: &mut UserInputContext<()>,
+    ) -> Result<(), ServerError> {
+        Ok(())
+    }
+
+    fn handle_set_ic_values(
+        &mut self,
+        _server: &mut S,
+        user_ic: &mut UserInputContext<()>,
+    ) -> Result<(), ServerError> {
+        self.record_spot(user_ic);
+        Ok(())
+    }
+
+    fn handle_forward_event(
+        &mut self,
+        server: &mut S,
+        user_ic: &mut UserInputContext<()>,
+        xev: &KeyPressEvent,
+    ) -> Result<bool, ServerError> {
+        if xev.response_type & 0x7f == KEY_RELEASE_EVENT || xev.detail == KEY_ESCAPE {
+            return Ok(false);
+        }
+        if xev.detail == KEY_RETURN {
+            server.preedit_draw(&mut user_ic.ic, "")?;
+            server.commit(&user_ic.ic, "日本語")?;
+        } else {
+            server.preedit_draw(&mut user_ic.ic, "にほん")?;
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,143 @@
+//! Checks choosing items from, and dismissing, context menus.
+
+#![cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+
+mod common;
+
+use common::{open_editor, wait_until, Editor, XServer};
+use vst_window::{ContextMenu, MenuItem, WindowEvent};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::ConnectionExt as _;
+
+const SIZE_XY: (i32, i32) = (300, 200);
+
+fn items() -> Vec<MenuItem> {
+    vec![
+        MenuItem::new(1, "MIDI learn"),
+        MenuItem::new(2, "Reset"),
+        MenuItem::Separator,
+        MenuItem::new(3, "Copy value"),
+    ]
+}
+
+/// Polls `menu` until it has grabbed the pointer, so that clicks outside of it are reported.
+fn wait_until_grabbed(server: &XServer, menu: &ContextMenu) {
+    assert!(wait_until(|| {
+        assert_eq!(menu.poll_event(), None);
+        server.is_pointer_grabbed()
+    }));
+}
+
+fn wait_for_event(menu: &ContextMenu) -> Option<WindowEvent> {
+    let mut event = None;
+    wait_until(|| {
+        event = menu.poll_event();
+        event.is_some()
+    });
+    event
+}
+
+#[test]
+fn item_is_chosen_from_menu_at_cursor() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    server.move_pointer(child, 40, 30);
+    let menu = ContextMenu::show(&window, items()).unwrap();
+    wait_until_grabbed(&server, &menu);
+
+    // Items are 24 pixels high, below a 1 pixel border and 3 pixels of padding, so the second one
+    // spans from 28 to 52 pixels below the top of the menu.
+    server.move_pointer(child, 40 + 20, 30 + 40);
+    server.press_button(1);
+    server.release_button(1);
+    assert_eq!(
+        wait_for_event(&menu),
+        Some(WindowEvent::MenuItemSelected(2))
+    );
+    assert!(!menu.is_open());
+    assert_eq!(menu.poll_event(), None);
+}
+
+#[test]
+fn clicking_outside_menu_dismisses_it() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let menu = ContextMenu::show_at(&window, (10, 10), items()).unwrap();
+    wait_until_grabbed(&server, &menu);
+
+    // Separators can't be chosen.
+    server.move_pointer(child, 10 + 20, 10 + 56);
+    server.press_button(1);
+    server.release_button(1);
+    server.move_pointer(child, 250, 150);
+    server.press_button(1);
+    server.release_button(1);
+    assert_eq!(wait_for_event(&menu), Some(WindowEvent::PopupDismissed));
+    assert!(!menu.is_open());
+}
+
+#[test]
+fn menu_near_screen_corner_opens_towards_it() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    let root = server.connection.setup().roots[server.screen_num].root;
+    let screen = server
+        .connection
+        .get_geometry(root)
+        .unwrap()
+        .reply()
+        .unwrap();
+    let (width, height) = (screen.width as i16, screen.height as i16);
+    assert_eq!(
+        window.screen_size(),
+        Some((i32::from(width), i32::from(height)))
+    );
+
+    let (editor_x, editor_y) = window.screen_position().unwrap();
+    let corner = (width - 10, height - 10);
+    let position = (
+        i32::from(corner.0) - editor_x,
+        i32::from(corner.1) - editor_y,
+    );
+    let menu = ContextMenu::show_at(&window, position, items()).unwrap();
+    wait_until_grabbed(&server, &menu);
+
+    // The menu is 89 pixels high, so once flipped above the position, its second item spans from
+    // 61 to 37 pixels above it. Had it only been moved onto the screen, the first item would be
+    // 55 pixels above it instead.
+    server.move_pointer(root, corner.0 - 20, corner.1 - 55);
+    server.press_button(1);
+    server.release_button(1);
+    assert_eq!(
+        wait_for_event(&menu),
+        Some(WindowEvent::MenuItemSelected(2))
+    );
+}
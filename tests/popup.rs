@@ -0,0 +1,113 @@
+//! Checks the placement and dismissal of popup windows.
+
+#![cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+
+mod common;
+
+use common::{collect_events, open_editor, wait_until, window_id, Editor, XServer};
+use vst_window::{MouseButton, WindowEvent};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::ConnectionExt as _;
+
+const SIZE_XY: (i32, i32) = (300, 200);
+const POPUP_SIZE_XY: (i32, i32) = (100, 80);
+
+#[test]
+fn popup_is_placed_relative_to_editor() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let (popup, _popup_events) = window.create_popup((250, 150), POPUP_SIZE_XY).unwrap();
+    let popup_id = window_id(&popup);
+    server.wait_until_viewable(popup_id);
+
+    let root = server.connection.setup().roots[server.screen_num].root;
+    let attributes = server
+        .connection
+        .get_window_attributes(popup_id)
+        .unwrap()
+        .reply()
+        .unwrap();
+    assert!(attributes.override_redirect);
+    let geometry = server
+        .connection
+        .get_geometry(popup_id)
+        .unwrap()
+        .reply()
+        .unwrap();
+    assert_eq!(geometry.root, root);
+    assert_eq!((geometry.width, geometry.height), (100, 80));
+    // The popup isn't clipped to the editor's bounds.
+    let editor_origin = server
+        .connection
+        .translate_coordinates(child, root, 0, 0)
+        .unwrap()
+        .reply()
+        .unwrap();
+    let popup_origin = server
+        .connection
+        .translate_coordinates(popup_id, root, 0, 0)
+        .unwrap()
+        .reply()
+        .unwrap();
+    assert_eq!(
+        (popup_origin.dst_x, popup_origin.dst_y),
+        (editor_origin.dst_x + 250, editor_origin.dst_y + 150)
+    );
+}
+
+#[test]
+fn clicking_outside_popup_dismisses_it() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let (popup, popup_events) = window.create_popup((20, 20), POPUP_SIZE_XY).unwrap();
+    let popup_id = window_id(&popup);
+    server.wait_until_viewable(popup_id);
+
+    // The pointer is grabbed once the popup's event source is polled.
+    assert!(wait_until(|| {
+        while popup_events.poll_event().is_some() {}
+        server.is_pointer_grabbed()
+    }));
+
+    server.move_pointer(popup_id, 50, 40);
+    server.press_button(1);
+    server.release_button(1);
+    assert_eq!(
+        collect_events(&popup_events, 3),
+        [
+            WindowEvent::CursorMovement(0.5, 0.5),
+            WindowEvent::MouseClick(MouseButton::Left),
+            WindowEvent::MouseRelease(MouseButton::Left),
+        ]
+    );
+
+    // Outside of the popup, but inside the editor.
+    server.move_pointer(child, 250, 150);
+    server.press_button(1);
+    server.release_button(1);
+    let events = collect_events(&popup_events, 2);
+    assert!(events.contains(&WindowEvent::PopupDismissed));
+    assert!(!events.contains(&WindowEvent::MouseClick(MouseButton::Left)));
+}
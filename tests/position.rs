@@ -0,0 +1,69 @@
+//! Checks querying and changing the position of the editor's window.
+
+#![cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+
+mod common;
+
+use common::{open_editor, wait_until, window_id, Editor, XServer};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::ConnectionExt as _;
+
+const SIZE_XY: (i32, i32) = (300, 200);
+
+#[test]
+fn window_can_be_moved_within_parent() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let parent = server.create_parent_window((400, 300));
+    let (window, _event_source) = vst_window::setup(parent as *mut _, SIZE_XY);
+    let child = window_id(&window);
+    server.wait_until_viewable(child);
+    assert_eq!(window.position(), Some((0, 0)));
+
+    window.set_position(50, 40);
+    assert!(wait_until(|| window.position() == Some((50, 40))));
+    let geometry = server
+        .connection
+        .get_geometry(child)
+        .unwrap()
+        .reply()
+        .unwrap();
+    assert_eq!((geometry.x, geometry.y), (50, 40));
+
+    let root = server.connection.setup().roots[server.screen_num].root;
+    let parent_origin = server
+        .connection
+        .translate_coordinates(parent, root, 0, 0)
+        .unwrap()
+        .reply()
+        .unwrap();
+    assert_eq!(
+        window.screen_position(),
+        Some((
+            i32::from(parent_origin.dst_x) + 50,
+            i32::from(parent_origin.dst_y) + 40
+        ))
+    );
+}
+
+#[test]
+fn negative_positions_are_preserved() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    window.set_position(-20, -10);
+    assert!(wait_until(|| window.position() == Some((-20, -10))));
+}
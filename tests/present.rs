@@ -0,0 +1,243 @@
+//! Checks that CPU-rendered frames are drawn to the editor window.
+
+#![cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+
+mod common;
+
+use common::{open_editor, wait_until, Editor, XServer};
+use vst_window::{Image, PresentMethod, Rect};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::ConnectionExt as _;
+
+/// Large enough that a frame doesn't fit in a single request without BIG-REQUESTS.
+const SIZE_XY: (i32, i32) = (700, 500);
+
+fn gradient(width: u32, height: u32) -> Vec<u32> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| ((x % 256) << 16) | ((y % 256) << 8) | 0x80))
+        .collect()
+}
+
+#[test]
+fn presented_pixels_are_drawn_to_window() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let (width, height) = (SIZE_XY.0 as u32, SIZE_XY.1 as u32);
+    let frame = gradient(width, height);
+    window.present_pixels(&frame, width, height);
+
+    assert!(wait_until(|| server.get_pixels(
+        child,
+        width as u16,
+        height as u16
+    ) == frame));
+}
+
+#[test]
+fn smaller_frames_are_drawn_at_top_left() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let frame = vec![0x00ff_0000; 10 * 20];
+    window.present_pixels(&frame, 10, 20);
+
+    assert!(wait_until(|| {
+        let pixels = server.get_pixels(child, 11, 21);
+        (0..21).all(|y| {
+            (0..11).all(|x| {
+                let expected = if x < 10 && y < 20 { 0x00ff_0000 } else { 0 };
+                pixels[y * 11 + x] == expected
+            })
+        })
+    }));
+}
+
+#[test]
+fn shared_memory_is_used_when_available() {
+    let Some(server) = XServer::start_with_args(&["+extension", "MIT-SHM"]) else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    assert_eq!(window.present_method(), PresentMethod::SharedMemory);
+}
+
+#[test]
+fn put_image_is_used_without_mit_shm() {
+    let Some(server) = XServer::start_with_args(&["-extension", "MIT-SHM"]) else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    assert_eq!(window.present_method(), PresentMethod::PutImage);
+
+    let (width, height) = (SIZE_XY.0 as u32, SIZE_XY.1 as u32);
+    let frame = gradient(width, height);
+    window.present_pixels(&frame, width, height);
+
+    assert!(wait_until(|| server.get_pixels(
+        child,
+        width as u16,
+        height as u16
+    ) == frame));
+}
+
+#[test]
+fn frames_larger_than_window_are_clipped() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    // Presenting a smaller frame first means a larger frame needs a new shared memory segment.
+    window.present_pixels(&gradient(10, 10), 10, 10);
+    let (width, height) = (SIZE_XY.0 as u32 + 100, SIZE_XY.1 as u32 + 100);
+    let frame = gradient(width, height);
+    window.present_pixels(&frame, width, height);
+
+    let expected: Vec<u32> = frame
+        .chunks_exact(width as usize)
+        .take(SIZE_XY.1 as usize)
+        .flat_map(|row| row[..SIZE_XY.0 as usize].iter().copied())
+        .collect();
+    assert!(wait_until(|| server.get_pixels(
+        child,
+        SIZE_XY.0 as u16,
+        SIZE_XY.1 as u16
+    ) == expected));
+}
+
+#[test]
+fn only_dirty_and_exposed_rects_are_redrawn() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let (width, height) = (SIZE_XY.0 as u32, SIZE_XY.1 as u32);
+    let first = vec![0x0000_00ff; (width * height) as usize];
+    let second = vec![0x00ff_0000; (width * height) as usize];
+    let is_within = |rect: &Rect, i: usize| {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        x >= rect.x && x < rect.x + rect.width && y >= rect.y && y < rect.y + rect.height
+    };
+
+    while event_source.poll_event().is_some() {}
+    window.present_pixels(&first, width, height);
+
+    let dirty = Rect {
+        x: 10,
+        y: 20,
+        width: 30,
+        height: 40,
+    };
+    window.present_dirty_pixels(&second, width, height, &[dirty]);
+    assert!(wait_until(|| {
+        let pixels = server.get_pixels(child, width as u16, height as u16);
+        pixels.iter().enumerate().all(|(i, &pixel)| {
+            pixel
+                == if is_within(&dirty, i) {
+                    0x00ff_0000
+                } else {
+                    0x0000_00ff
+                }
+        })
+    }));
+
+    // Clearing an area generates an exposure, which must be redrawn even though the frame has no
+    // dirty rects of its own.
+    let exposed = Rect {
+        x: 100,
+        y: 100,
+        width: 50,
+        height: 60,
+    };
+    server
+        .connection
+        .clear_area(
+            true,
+            child,
+            exposed.x as i16,
+            exposed.y as i16,
+            exposed.width as u16,
+            exposed.height as u16,
+        )
+        .unwrap();
+    server.connection.flush().unwrap();
+    assert!(wait_until(|| {
+        while event_source.poll_event().is_some() {}
+        window.present_dirty_pixels(&second, width, height, &[]);
+        let pixels = server.get_pixels(child, width as u16, height as u16);
+        pixels.iter().enumerate().all(|(i, &pixel)| {
+            let redrawn = is_within(&dirty, i) || is_within(&exposed, i);
+            pixel == if redrawn { 0x00ff_0000 } else { 0x0000_00ff }
+        })
+    }));
+}
+
+#[test]
+fn captured_image_matches_presented_frame() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window,
+        event_source: _event_source,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let (width, height) = (SIZE_XY.0 as u32, SIZE_XY.1 as u32);
+    let frame = gradient(width, height);
+    window.present_pixels(&frame, width, height);
+
+    let expected = Image {
+        width,
+        height,
+        data: frame
+            .iter()
+            .flat_map(|pixel| {
+                let [_, r, g, b] = pixel.to_be_bytes();
+                [r, g, b, 0xff]
+            })
+            .collect(),
+    };
+    assert!(wait_until(|| window.capture().as_ref() == Some(&expected)));
+}
@@ -0,0 +1,118 @@
+//! Checks that recorded `WindowEvent` streams can be played back faithfully.
+
+#![cfg(feature = "record")]
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+mod common;
+
+use std::time::{Duration, Instant};
+
+use vst_window::{MouseButton, ReplayEventSource, WindowEvent};
+
+const RECORDING: &str = r#"{"time":{"secs":0,"nanos":0},"event":{"CursorMovement":[0.5,0.25]}}
+{"time":{"secs":0,"nanos":0},"event":{"MouseClick":"Left"}}
+{"time":{"secs":0,"nanos":200000000},"event":{"MouseRelease":"Left"}}
+"#;
+
+#[test]
+fn replay_preserves_original_pacing() {
+    let replay = ReplayEventSource::from_reader(RECORDING.as_bytes()).unwrap();
+    let start = Instant::now();
+
+    assert_eq!(
+        replay.poll_event(),
+        Some(WindowEvent::CursorMovement(0.5, 0.25))
+    );
+    assert_eq!(
+        replay.poll_event(),
+        Some(WindowEvent::MouseClick(MouseButton::Left))
+    );
+    assert_eq!(replay.poll_event(), None);
+    assert!(!replay.is_finished());
+
+    let release = loop {
+        if let Some(event) = replay.poll_event() {
+            break event;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!(release, WindowEvent::MouseRelease(MouseButton::Left));
+    assert!(replay.is_finished());
+}
+
+#[test]
+fn replay_without_pacing_is_immediate() {
+    let replay = ReplayEventSource::from_reader(RECORDING.as_bytes())
+        .unwrap()
+        .without_pacing();
+
+    let mut events = vec![];
+    while let Some(event) = replay.poll_event() {
+        events.push(event);
+    }
+    assert_eq!(
+        events,
+        vec![
+            WindowEvent::CursorMovement(0.5, 0.25),
+            WindowEvent::MouseClick(MouseButton::Left),
+            WindowEvent::MouseRelease(MouseButton::Left),
+        ]
+    );
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+#[test]
+fn recorded_events_replay_identically() {
+    use common::{open_editor, Editor, XServer};
+    use vst_window::EventRecorder;
+
+    const SIZE_XY: (i32, i32) = (300, 200);
+
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+
+    let recorder = EventRecorder::new(event_source, vec![]);
+    server.move_pointer(child, 30, 40);
+    server.press_button(3);
+    server.move_pointer(child, 60, 80);
+    server.release_button(3);
+
+    let mut live = vec![];
+    assert!(common::wait_until(|| {
+        while let Some(event) = recorder.poll_event() {
+            live.push(event);
+        }
+        live.len() >= 4
+    }));
+    let (event_source, recording) = recorder.finish().unwrap();
+    assert_eq!(event_source.poll_event(), None);
+
+    let replay = ReplayEventSource::from_reader(&recording[..])
+        .unwrap()
+        .without_pacing();
+    let mut replayed = vec![];
+    while let Some(event) = replay.poll_event() {
+        replayed.push(event);
+    }
+    assert_eq!(replayed, live);
+}
@@ -0,0 +1,69 @@
+//! Checks that an `EventStream` is woken up by new events.
+
+#![cfg(all(
+    feature = "async",
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    )
+))]
+
+mod common;
+
+use std::pin::Pin;
+use std::time::Duration;
+
+use common::{open_editor, Editor, XServer};
+use futures_core::Stream;
+use vst_window::{EventStream, MouseButton, WindowEvent};
+
+const SIZE_XY: (i32, i32) = (300, 200);
+
+async fn next(stream: &mut EventStream) -> Option<WindowEvent> {
+    std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+#[test]
+fn stream_yields_events_as_they_arrive() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    while event_source.poll_event().is_some() {}
+
+    let mut stream = event_source.into_stream().unwrap();
+    let waker = stream.get_ref().waker();
+    let thread = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        waker.wake(5);
+    });
+    assert_eq!(
+        async_io::block_on(next(&mut stream)),
+        Some(WindowEvent::User(5))
+    );
+    thread.join().unwrap();
+
+    server.move_pointer(child, 150, 100);
+    server.press_button(1);
+    let events =
+        async_io::block_on(async { vec![next(&mut stream).await, next(&mut stream).await] });
+    assert_eq!(
+        events,
+        vec![
+            Some(WindowEvent::CursorMovement(0.5, 0.5)),
+            Some(WindowEvent::MouseClick(MouseButton::Left)),
+        ]
+    );
+    server.release_button(1);
+
+    let event_source = stream.into_inner().unwrap();
+    drop(event_source);
+}
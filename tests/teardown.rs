@@ -0,0 +1,87 @@
+//! Checks that closing an editor releases the X resources it allocated.
+
+#![cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+
+mod common;
+
+use common::{wait_until, window_id, XServer};
+
+const SIZE_XY: (i32, i32) = (300, 200);
+
+#[test]
+fn reopening_editor_does_not_leak_resources() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let parent = server.create_parent_window(SIZE_XY);
+    let baseline = server.total_resource_counts();
+
+    for _ in 0..50 {
+        let (window, event_source) = vst_window::setup(parent as *mut _, SIZE_XY);
+        while event_source.poll_event().is_some() {}
+        drop(window);
+        drop(event_source);
+    }
+
+    assert!(
+        wait_until(|| server.total_resource_counts() == baseline),
+        "expected {:?}, found {:?}",
+        baseline,
+        server.total_resource_counts()
+    );
+}
+
+#[test]
+fn dropping_window_frees_resources_while_event_source_lives() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let parent = server.create_parent_window(SIZE_XY);
+
+    for _ in 0..10 {
+        let (window, event_source) = vst_window::setup(parent as *mut _, SIZE_XY);
+        let xid = window_id(&window);
+        server.sync();
+        let counts = server.client_resource_counts(xid).unwrap();
+        assert_eq!(counts.get("WINDOW"), Some(&1));
+        assert_eq!(counts.get("GC"), Some(&1));
+
+        drop(window);
+        assert!(wait_until(|| {
+            let counts = server.client_resource_counts(xid).unwrap();
+            counts.get("WINDOW").copied().unwrap_or(0) == 0
+                && counts.get("GC").copied().unwrap_or(0) == 0
+        }));
+
+        drop(event_source);
+        assert!(wait_until(|| server.client_resource_counts(xid).is_none()));
+    }
+}
+
+#[test]
+fn host_destroying_parent_first_is_harmless() {
+    use x11rb::protocol::xproto::ConnectionExt as _;
+
+    let Some(server) = XServer::start() else {
+        return;
+    };
+
+    for _ in 0..10 {
+        let parent = server.create_parent_window(SIZE_XY);
+        let (window, event_source) = vst_window::setup(parent as *mut _, SIZE_XY);
+        let xid = window_id(&window);
+
+        server.connection.destroy_window(parent).unwrap();
+        server.sync();
+        drop(window);
+        while event_source.poll_event().is_some() {}
+        drop(event_source);
+        assert!(wait_until(|| server.client_resource_counts(xid).is_none()));
+    }
+}
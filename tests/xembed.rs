@@ -0,0 +1,158 @@
+//! Checks the client side of the XEmbed protocol, with the test acting as the embedder.
+
+#![cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+
+mod common;
+
+use common::{collect_events, open_editor, wait_until, window_id, Editor, XServer};
+use vst_window::{MouseButton, WindowEvent};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    ChangeWindowAttributesAux, ClientMessageEvent, ConnectionExt as _, EventMask, MapState, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::CURRENT_TIME;
+
+const SIZE_XY: (i32, i32) = (300, 200);
+
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+const XEMBED_REQUEST_FOCUS: u32 = 3;
+const XEMBED_FOCUS_IN: u32 = 4;
+const XEMBED_FOCUS_OUT: u32 = 5;
+
+fn send_xembed(server: &XServer, window: Window, message: u32, data1: u32) {
+    let xembed = server.atom("_XEMBED");
+    let event = ClientMessageEvent::new(32, window, xembed, [CURRENT_TIME, message, 0, data1, 0]);
+    server
+        .connection
+        .send_event(false, window, EventMask::NO_EVENT, event)
+        .unwrap();
+    server.sync();
+}
+
+fn xembed_info(server: &XServer, window: Window) -> Option<Vec<u32>> {
+    let xembed_info = server.atom("_XEMBED_INFO");
+    let reply = server
+        .connection
+        .get_property(false, window, xembed_info, xembed_info, 0, 2)
+        .unwrap()
+        .reply()
+        .unwrap();
+    reply.value32().map(|values| values.collect())
+}
+
+#[test]
+fn xembed_info_is_set_before_the_window_is_mapped() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    // Like a `GtkSocket`, the embedder redirects the window's map request, and maps it itself.
+    let parent = server.create_parent_window(SIZE_XY);
+    server
+        .connection
+        .change_window_attributes(
+            parent,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::SUBSTRUCTURE_REDIRECT),
+        )
+        .unwrap();
+    server.sync();
+    let (window, _event_source) = vst_window::setup(parent as *mut _, SIZE_XY);
+    let child = window_id(&window);
+
+    let mut map_requested = false;
+    assert!(wait_until(|| {
+        while let Some(event) = server.connection.poll_for_event().unwrap() {
+            if let Event::MapRequest(request) = event {
+                map_requested |= request.window == child;
+            }
+        }
+        map_requested
+    }));
+    let attributes = server
+        .connection
+        .get_window_attributes(child)
+        .unwrap()
+        .reply()
+        .unwrap();
+    assert_eq!(attributes.map_state, MapState::UNMAPPED);
+    // Version 0, with the `XEMBED_MAPPED` flag.
+    assert_eq!(xembed_info(&server, child), Some(vec![0, 1]));
+
+    server.connection.map_window(child).unwrap();
+    server.wait_until_viewable(child);
+}
+
+#[test]
+fn focus_handoff_is_reported() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        parent,
+        window: _window,
+        event_source,
+        child,
+    } = open_editor(&server, SIZE_XY);
+
+    // Ignored until enabled.
+    send_xembed(&server, child, XEMBED_FOCUS_IN, 0);
+    assert_eq!(event_source.poll_event(), None);
+
+    event_source.set_xembed_enabled(true);
+    send_xembed(&server, child, XEMBED_EMBEDDED_NOTIFY, parent);
+    send_xembed(&server, child, XEMBED_FOCUS_IN, 0);
+    send_xembed(&server, child, XEMBED_FOCUS_IN, 0);
+    send_xembed(&server, child, XEMBED_FOCUS_OUT, 0);
+    assert_eq!(
+        collect_events(&event_source, 2),
+        vec![WindowEvent::Focused(true), WindowEvent::Focused(false)]
+    );
+}
+
+#[test]
+fn clicking_requests_focus_from_embedder() {
+    let Some(server) = XServer::start() else {
+        return;
+    };
+    let Editor {
+        window: _window,
+        event_source,
+        child,
+        ..
+    } = open_editor(&server, SIZE_XY);
+    let embedder = server.create_hidden_window();
+
+    event_source.set_xembed_enabled(true);
+    send_xembed(&server, child, XEMBED_EMBEDDED_NOTIFY, embedder);
+    server.move_pointer(child, 150, 100);
+    server.press_button(1);
+    server.release_button(1);
+    assert_eq!(
+        collect_events(&event_source, 3),
+        vec![
+            WindowEvent::CursorMovement(0.5, 0.5),
+            WindowEvent::MouseClick(MouseButton::Left),
+            WindowEvent::MouseRelease(MouseButton::Left),
+        ]
+    );
+
+    let xembed = server.atom("_XEMBED");
+    let mut requested = false;
+    assert!(wait_until(|| {
+        while let Some(event) = server.connection.poll_for_event().unwrap() {
+            if let Event::ClientMessage(message) = event {
+                let data = message.data.as_data32();
+                if message.window == embedder && message.type_ == xembed {
+                    requested |= data[1] == XEMBED_REQUEST_FOCUS;
+                }
+            }
+        }
+        requested
+    }));
+}